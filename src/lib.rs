@@ -0,0 +1,7807 @@
+use ::crossterm::event::*;
+use ::crossterm::terminal::ClearType;
+use ::crossterm::style::*;
+use ::crossterm::{cursor, event, execute, queue, style, terminal, Command};
+use arboard::Clipboard;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{self, stdout, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use std::{cmp, env, fs, iter, mem, process, thread};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// The editor itself. The binary only sets up the terminal and
+// runs it, everything here can be driven without one
+
+// PROMPT MACRO TEXTUAL DEFINITION
+#[macro_export]
+macro_rules! prompt {
+    ($output:expr,$reader:expr,$args:tt) => {
+        prompt!($output, $reader, $args, initial = String::new())
+    };
+
+    // The input starts out as initial, which can be edited
+    // like anything typed
+    ($output:expr,$reader:expr,$args:tt, initial = $initial:expr) => {
+        prompt!($output, $reader, $args, initial = $initial, paths = false)
+    };
+
+    // With paths, Tab completes the file path being typed
+    ($output:expr,$reader:expr,$args:tt, paths = $paths:expr) => {
+        prompt!($output, $reader, $args, initial = String::new(), paths = $paths)
+    };
+
+    ($output:expr,$reader:expr,$args:tt, initial = $initial:expr, paths = $paths:expr) => {
+        prompt!(
+            $output,
+            $reader,
+            $args,
+            callback = |&_, _, _| {},
+            history = &mut Vec::new(),
+            initial = $initial,
+            edit_keys = true,
+            paths = $paths
+        )
+    };
+
+    // Answers given are added to history, Ctrl+P and Ctrl+N
+    // step back and forward through them
+    ($output:expr,$reader:expr,$args:tt, history = $history:expr) => {
+        prompt!(
+            $output,
+            $reader,
+            $args,
+            callback = |&_, _, _| {},
+            history = $history,
+            initial = String::new(),
+            edit_keys = true,
+            paths = false
+        )
+    };
+
+    // A callback gets the arrow keys, so they don't move
+    // through the input like in other prompts
+    ($output:expr,$reader:expr,$args:tt, callback = $callback:expr) => {
+        prompt!($output, $reader, $args, callback = $callback, history = &mut Vec::new())
+    };
+
+    ($output:expr,$reader:expr,$args:tt, callback = $callback:expr, history = $history:expr) => {
+        prompt!(
+            $output,
+            $reader,
+            $args,
+            callback = $callback,
+            history = $history,
+            initial = String::new(),
+            edit_keys = false,
+            paths = false
+        )
+    };
+
+    (
+        $output:expr,
+        $reader:expr,
+        $args:tt,
+        callback = $callback:expr,
+        history = $history:expr,
+        initial = $initial:expr,
+        edit_keys = $edit_keys:expr,
+        paths = $paths:expr
+    ) => {{
+        let output: &mut Output = $output;
+        let reader: &mut Reader = $reader;
+        let history: &mut Vec<String> = $history;
+        let mut input: String = $initial;
+        // Character of the input that typing goes in front of
+        let mut position = input.chars().count();
+        // Position in history of the answer being shown
+        let mut recalled: Option<usize> = None;
+        // Paths the last Tab could complete the input to, and
+        // which of them it was completed to when going through
+        // them one at a time
+        let mut completions: Option<(Vec<String>, Option<usize>)> = None;
+
+        loop {
+            // The prompt stays up however long it takes to answer
+            output
+                .status_message
+                .set_message_for(format!($args, input), None);
+            if $edit_keys {
+                // The input's column in the message is found by
+                // filling it in with a character it doesn't have
+                let marked = format!($args, '\0');
+                let before: String = input.chars().take(position).collect();
+                output.prompt_cursor =
+                    Some(marked.split('\0').next().unwrap_or_default().width() + before.width());
+            }
+            output.refresh_screen()?;
+
+            // Pasted text goes into the input without its line
+            // breaks and the callback sees it like a typed key.
+            // The cursor stays in the prompt if the screen is
+            // redrawn while waiting
+            let event = output.read_input(reader);
+            output.prompt_cursor = None;
+            let key_event = match event? {
+                Event::Key(key_event) => key_event,
+                Event::Paste(text) => {
+                    text.chars().filter(|ch| !ch.is_control()).for_each(|ch| {
+                        input.insert(char_byte_index(&input, position), ch);
+                        position += 1;
+                    });
+                    $callback(output, &input, KeyCode::Null);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let completing = completions.take();
+            match key_event {
+                // The first Tab completes as much as all the
+                // matching paths have in common, the ones after
+                // it go through them
+                KeyEvent {
+                    code: KeyCode::Tab,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $paths => {
+                    let (paths, shown) = match completing {
+                        Some(completing) => completing,
+                        None => (complete_path(&input), None),
+                    };
+                    let common = common_prefix(&paths);
+                    let shown = match shown {
+                        _ if paths.len() == 1 => None,
+                        None if common.chars().count() > input.chars().count() => None,
+                        None => Some(0),
+                        Some(index) => Some((index + 1) % paths.len()),
+                    };
+                    if !paths.is_empty() {
+                        input = shown.map_or(common, |index| paths[index].clone());
+                        position = input.chars().count();
+                    }
+                    if paths.len() > 1 {
+                        completions = Some((paths, shown));
+                    }
+                }
+                // Only the find prompt has a callback, which
+                // toggles case with Tab, the others type it
+                KeyEvent {
+                    code: KeyCode::Tab,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $edit_keys => {
+                    input.insert(char_byte_index(&input, position), '\t');
+                    position += 1;
+                }
+                KeyEvent {
+                    code: KeyCode::Enter,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if !input.is_empty() => {
+                    output.status_message.set_message(String::new());
+                    history.retain(|answer| *answer != input);
+                    history.push(input.clone());
+                    if history.len() > PROMPT_HISTORY {
+                        history.remove(0);
+                    }
+                    $callback(output, &input, KeyCode::Enter);
+                    break;
+                }
+                KeyEvent {
+                    code: KeyCode::Esc,
+                    ..
+                } => {
+                    output.status_message.set_message(String::new());
+                    input.clear();
+                    $callback(output, &input, KeyCode::Esc);
+                    break;
+                }
+                // Delete at the end of the input works like
+                // Backspace, as it did before the input could
+                // be edited in the middle
+                KeyEvent {
+                    code: KeyCode::Delete,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if position < input.chars().count() => {
+                    input.remove(char_byte_index(&input, position));
+                }
+                KeyEvent {
+                    code: KeyCode::Backspace | KeyCode::Delete,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if position > 0 => {
+                    position -= 1;
+                    input.remove(char_byte_index(&input, position));
+                }
+                KeyEvent {
+                    code: KeyCode::Left,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $edit_keys => position = position.saturating_sub(1),
+                KeyEvent {
+                    code: KeyCode::Right,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $edit_keys => position = cmp::min(position + 1, input.chars().count()),
+                KeyEvent {
+                    code: KeyCode::Home,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $edit_keys => position = 0,
+                KeyEvent {
+                    code: KeyCode::End,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if $edit_keys => position = input.chars().count(),
+                KeyEvent {
+                    code: KeyCode::Char('p'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => {
+                    if let Some(index) = recalled.unwrap_or(history.len()).checked_sub(1) {
+                        recalled = Some(index);
+                        input = history[index].clone();
+                        position = input.chars().count();
+                    }
+                }
+                KeyEvent {
+                    code: KeyCode::Char('n'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => {
+                    if let Some(index) = recalled {
+                        recalled = Some(index + 1).filter(|&index| index < history.len());
+                        input = recalled.map_or_else(String::new, |index| history[index].clone());
+                        position = input.chars().count();
+                    }
+                }
+                KeyEvent {
+                    code: KeyCode::Char(ch),
+                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                    ..
+                } => {
+                    input.insert(char_byte_index(&input, position), ch);
+                    position += 1;
+                }
+                _ => {}
+            }
+
+            $callback(output, &input, key_event.code);
+        }
+
+        if input.is_empty() {None} else {Some(input)}
+    }};
+}
+
+// Converts a character index into the byte offset String
+// methods expect, the end of the text if it's past it
+fn char_byte_index(text: &str, at: usize) -> usize {
+    text.char_indices()
+        .nth(at)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+// The paths input could be completed to, sorted. Only the part
+// after the last slash is completed, directories end in a slash
+// and hidden files are left out unless that part starts with a dot
+fn complete_path(input: &str) -> Vec<String> {
+    let (directory, prefix) = match input.rfind('/') {
+        Some(index) => input.split_at(index + 1),
+        None => ("", input),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { "." } else { directory }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Following the entry so a link to a directory
+            // completes like one
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", directory, name, slash))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+// The longest text all the paths start with
+fn common_prefix(paths: &[String]) -> String {
+    let (first, rest) = match paths.split_first() {
+        Some(split) => split,
+        None => return String::new(),
+    };
+    let mut common = first.as_str();
+    for path in rest {
+        let length = common
+            .char_indices()
+            .zip(path.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(path.len()), |((index, _), _)| index);
+        common = &common[..length];
+    }
+    common.to_string()
+}
+
+// How many answers a prompt's history keeps
+const PROMPT_HISTORY: usize = 20;
+
+const VERSION: f32 = 1.0;
+
+const DEFAULT_TAB_STOP: usize = 8;
+const DEFAULT_SCROLLOFF: usize = 3;
+const DEFAULT_SCROLL_LINES: usize = 3;
+const DEFAULT_STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+// Warnings about losing unsaved changes stay up longer
+const WARNING_TIMEOUT: Duration = Duration::from_secs(10);
+// How long without a key press before unsaved changes
+// are written to the recovery file
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+const DEFAULT_RULER: usize = 80;
+
+// How the line number gutter is drawn. Relative shows
+// the distance to the cursor line on every other line
+#[derive(Clone, Copy, PartialEq)]
+enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+}
+
+impl LineNumbers {
+    fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        }
+    }
+}
+
+// What happens when a key can't do anything, like Left at
+// the start of the file or a search that finds nothing
+#[derive(Clone, Copy, PartialEq)]
+enum Bell {
+    Off,
+    // Flashes the status bar
+    Visual,
+    // Sends the terminal's bell character
+    Audible,
+}
+
+// How long the visual bell keeps the status bar flashed
+const BELL_FLASH: Duration = Duration::from_millis(100);
+
+// The line ending written between rows when saving
+#[derive(Clone, Copy, PartialEq)]
+enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    // Picks whichever ending is used by most lines
+    // of the file, files without any lines use \n
+    fn detect(contents: &str) -> Self {
+        let crlf = contents.matches("\r\n").count();
+        let lf = contents.matches('\n').count() - crlf;
+        if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+// How many colors the terminal can show. Theme colors it
+// can't show are swapped for the nearest one it can
+#[derive(Clone, Copy, PartialEq)]
+enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+// The 16 basic colors as xterm draws them by default
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// Levels of each channel in the 6x6x6 cube of the 256
+// color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    // Guesses from the environment the way most terminal
+    // programs do: COLORTERM is only set by terminals with
+    // truecolor, and TERM names the 256 color ones
+    fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    fn parse(value: &toml::Value) -> Option<Option<Self>> {
+        match (value.as_integer(), value.as_str()) {
+            (Some(16), _) | (_, Some("16")) => Some(Some(ColorDepth::Ansi16)),
+            (Some(256), _) | (_, Some("256")) => Some(Some(ColorDepth::Ansi256)),
+            (_, Some("truecolor" | "24bit")) => Some(Some(ColorDepth::TrueColor)),
+            (_, Some("auto")) => Some(None),
+            _ => None,
+        }
+    }
+
+    // The color itself if the terminal can show it, otherwise
+    // the closest one it can
+    fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) => color,
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(Self::nearest_palette((r, g, b)))
+            }
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => Self::nearest_ansi((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) => match ANSI_COLORS.get(value as usize) {
+                Some(&(ansi, _)) => ansi,
+                None => Self::nearest_ansi(Self::palette_rgb(value)),
+            },
+            _ => color,
+        }
+    }
+
+    fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+        [(r1, r2), (g1, g2), (b1, b2)]
+            .iter()
+            .map(|&(a, b)| (a.abs_diff(b) as u32).pow(2))
+            .sum()
+    }
+
+    fn nearest_ansi(rgb: (u8, u8, u8)) -> Color {
+        ANSI_COLORS
+            .iter()
+            .min_by_key(|(_, ansi)| Self::distance(rgb, *ansi))
+            .map_or(Color::Reset, |&(color, _)| color)
+    }
+
+    // The closest entry of the color cube or of the grey ramp
+    // after it, the first 16 entries differ between terminals
+    fn nearest_palette(rgb: (u8, u8, u8)) -> u8 {
+        (16..=255)
+            .min_by_key(|&value| Self::distance(rgb, Self::palette_rgb(value)))
+            .unwrap_or(16)
+    }
+
+    // What a theme color looks like, for exported HTML. None
+    // for the terminal's own colors, which can't be known
+    fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+        match color {
+            Color::Rgb { r, g, b } => Some((r, g, b)),
+            Color::AnsiValue(value) => Some(Self::palette_rgb(value)),
+            _ => ANSI_COLORS
+                .iter()
+                .find(|(ansi, _)| *ansi == color)
+                .map(|&(_, rgb)| rgb),
+        }
+    }
+
+    fn palette_rgb(value: u8) -> (u8, u8, u8) {
+        match value {
+            0..=15 => ANSI_COLORS[value as usize].1,
+            16..=231 => {
+                let cube = value - 16;
+                (
+                    CUBE_LEVELS[(cube / 36) as usize],
+                    CUBE_LEVELS[(cube / 6 % 6) as usize],
+                    CUBE_LEVELS[(cube % 6) as usize],
+                )
+            }
+            _ => {
+                let grey = 8 + (value - 232) * 10;
+                (grey, grey, grey)
+            }
+        }
+    }
+}
+
+// Colors everything is drawn with, set in the [theme] table
+// of the config. The defaults are the editor's usual look
+#[derive(Clone, PartialEq)]
+struct Theme {
+    number: Color,
+    string: Color,
+    comment: Color,
+    keyword: Color,
+    line_numbers: Color,
+    current_line: Color,
+    ruler: Color,
+    search_match: Color,
+    current_match: Color,
+    // Shown whitespace, the markers of rows that go on past
+    // the edge of the screen and the scrollbar's track
+    dim: Color,
+    // The part of the scrollbar standing for the rows on screen
+    scrollbar: Color,
+    // The status bar while the visual bell flashes it
+    bell: Color,
+    // Background of spaces and tabs at the end of rows
+    trailing_whitespace: Color,
+    // The status bar and the selection are drawn in reverse
+    // video unless they're given colors
+    status_bar: Option<Color>,
+    status_bar_text: Option<Color>,
+    selection: Option<Color>,
+}
+
+impl Theme {
+    fn new() -> Self {
+        Self {
+            number: Color::Cyan,
+            string: Color::Green,
+            comment: Color::DarkGrey,
+            keyword: Color::Yellow,
+            line_numbers: Color::DarkGrey,
+            current_line: Color::AnsiValue(236),
+            ruler: Color::AnsiValue(238),
+            search_match: Color::DarkYellow,
+            current_match: Color::DarkMagenta,
+            dim: Color::DarkGrey,
+            scrollbar: Color::Grey,
+            bell: Color::DarkRed,
+            trailing_whitespace: Color::DarkRed,
+            status_bar: None,
+            status_bar_text: None,
+            selection: None,
+        }
+    }
+
+    // Sets the colors found in the table. A color is a name
+    // like "dark_cyan", a 256 color palette number or "#rrggbb"
+    fn apply(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table {
+            let color = Self::parse_color(value).ok_or_else(|| {
+                format!("theme.{} must be a color name, a number up to 255 or #rrggbb", key)
+            })?;
+            match key.as_str() {
+                "number" => self.number = color,
+                "string" => self.string = color,
+                "comment" => self.comment = color,
+                "keyword" => self.keyword = color,
+                "line_numbers" => self.line_numbers = color,
+                "current_line" => self.current_line = color,
+                "ruler" => self.ruler = color,
+                "search_match" => self.search_match = color,
+                "current_match" => self.current_match = color,
+                "dim" => self.dim = color,
+                "scrollbar" => self.scrollbar = color,
+                "bell" => self.bell = color,
+                "trailing_whitespace" => self.trailing_whitespace = color,
+                "status_bar" => self.status_bar = Some(color),
+                "status_bar_text" => self.status_bar_text = Some(color),
+                "selection" => self.selection = Some(color),
+                _ => return Err(format!("there's no theme color called {}", key)),
+            }
+        }
+        Ok(())
+    }
+
+    // The theme with every color swapped for one the terminal
+    // can show. The default current line and ruler colors are
+    // left as they are, 16 color terminals have always drawn
+    // them their own way and fitting would make them black
+    fn fit(&self, depth: ColorDepth) -> Self {
+        let fit = |color| depth.fit(color);
+        let default = Theme::new();
+        let fit_set = |color, default| if color == default { color } else { fit(color) };
+        Self {
+            number: fit(self.number),
+            string: fit(self.string),
+            comment: fit(self.comment),
+            keyword: fit(self.keyword),
+            line_numbers: fit(self.line_numbers),
+            current_line: fit_set(self.current_line, default.current_line),
+            ruler: fit_set(self.ruler, default.ruler),
+            search_match: fit(self.search_match),
+            current_match: fit(self.current_match),
+            dim: fit(self.dim),
+            scrollbar: fit(self.scrollbar),
+            bell: fit(self.bell),
+            trailing_whitespace: fit(self.trailing_whitespace),
+            status_bar: self.status_bar.map(fit),
+            status_bar_text: self.status_bar_text.map(fit),
+            selection: self.selection.map(fit),
+        }
+    }
+
+    fn parse_color(value: &toml::Value) -> Option<Color> {
+        if let Some(number) = value.as_integer() {
+            return u8::try_from(number).ok().map(Color::AnsiValue);
+        }
+        let name = value.as_str()?;
+        match name.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 => {
+                let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                Some(Color::Rgb {
+                    r: channel(0)?,
+                    g: channel(2)?,
+                    b: channel(4)?,
+                })
+            }
+            Some(_) => None,
+            None => Color::try_from(name).ok(),
+        }
+    }
+}
+
+// Settings read at startup from the config file
+// (~/.config/pound/config.toml). Anything not set
+// in the file keeps its default value
+#[derive(Clone)]
+pub struct Config {
+    tab_stop: usize,
+    // Insert spaces instead of a tab character
+    soft_tabs: bool,
+    line_numbers: LineNumbers,
+    // Insert the closing bracket or quote after an opening one
+    auto_close: bool,
+    // Strip trailing whitespace from every row when saving
+    trim_trailing_whitespace: bool,
+    // End the file with a newline when saving
+    trailing_newline: bool,
+    // Line ending used when saving, None keeps the
+    // one detected in the file
+    line_ending: Option<LineEnding>,
+    // Give the line the cursor is on a different background
+    highlight_current_line: bool,
+    // Column of the right margin guide, None hides it
+    ruler: Option<usize>,
+    // Draw spaces as a middle dot and tabs as an arrow
+    show_whitespace: bool,
+    // Give whitespace at the end of rows a red background,
+    // except on the row being typed on
+    highlight_trailing_whitespace: bool,
+    // Give up the last column of the text to a scrollbar
+    scrollbar: bool,
+    // Lines shown in the middle of the screen while the buffer
+    // is empty, {version} is filled in with the editor's version
+    welcome: Vec<String>,
+    // Underline words that aren't in the word list
+    spell_check: bool,
+    // Word list spell check reads, one word per line. Words
+    // added with Alt+A go in the words file next to the config
+    dictionary: PathBuf,
+    // How long status messages are shown, None keeps
+    // them until the next one
+    status_timeout: Option<Duration>,
+    // Rows kept visible above and below the cursor
+    scrolloff: usize,
+    // Rows a step of the mouse wheel scrolls by
+    scroll_lines: usize,
+    // Reopen files where the cursor was left in them
+    remember_position: bool,
+    // Start buffers in vim-like Normal mode, Esc goes back
+    // to it from Insert mode
+    modal: bool,
+    // What a key that can't do anything does
+    bell: Bell,
+    // Ask whether to save a modified buffer on Ctrl+Q, instead
+    // of having Ctrl+Q pressed again to close it anyway
+    confirm_quit: bool,
+    // Wrap long rows onto the following screen rows
+    soft_wrap: bool,
+    // Save after this long without a key press, None
+    // turns autosaving off
+    autosave: Option<Duration>,
+    // Run the buffer through its file type's formatter
+    // when saving with Ctrl+S
+    format_on_save: bool,
+    // Formatter command for each file type, it's given the
+    // text on stdin and prints the formatted text
+    formatters: HashMap<String, String>,
+    theme: Theme,
+    // Colors the terminal can show, guessed from the
+    // environment unless the config sets it
+    color_depth: ColorDepth,
+    // Keys from the [keys] table, each standing in for the
+    // default key of the action bound to it
+    key_bindings: HashMap<KeyChord, KeyChord>,
+}
+
+// A key together with the modifiers held down with it
+type KeyChord = (KeyCode, KeyModifiers);
+
+// The editor actions the [keys] table of the config can
+// bind to another key, with the key each is on by default
+const KEY_ACTIONS: &[(&str, KeyChord)] = &[
+    ("quit", (KeyCode::Char('q'), KeyModifiers::CONTROL)),
+    ("quit_all", (KeyCode::Char('q'), KeyModifiers::ALT)),
+    ("save", (KeyCode::Char('s'), KeyModifiers::CONTROL)),
+    ("open", (KeyCode::Char('o'), KeyModifiers::CONTROL)),
+    ("find", (KeyCode::Char('f'), KeyModifiers::CONTROL)),
+    ("find_next", (KeyCode::F(3), KeyModifiers::NONE)),
+    ("find_previous", (KeyCode::F(3), KeyModifiers::SHIFT)),
+    ("replace", (KeyCode::Char('r'), KeyModifiers::CONTROL)),
+    ("goto", (KeyCode::Char('l'), KeyModifiers::CONTROL)),
+    ("copy", (KeyCode::Char('c'), KeyModifiers::CONTROL)),
+    ("paste", (KeyCode::Char('v'), KeyModifiers::CONTROL)),
+    ("duplicate_line", (KeyCode::Char('d'), KeyModifiers::CONTROL)),
+    ("delete_line", (KeyCode::Char('k'), KeyModifiers::CONTROL)),
+    ("join_lines", (KeyCode::Char('j'), KeyModifiers::CONTROL)),
+    ("delete_word", (KeyCode::Char('w'), KeyModifiers::CONTROL)),
+    ("center", (KeyCode::Char('e'), KeyModifiers::CONTROL)),
+    ("scroll_to_top", (KeyCode::Char('t'), KeyModifiers::CONTROL)),
+    ("line_numbers", (KeyCode::Char('n'), KeyModifiers::CONTROL)),
+    ("undo", (KeyCode::Char('z'), KeyModifiers::CONTROL)),
+    ("redo", (KeyCode::Char('y'), KeyModifiers::CONTROL)),
+    ("toggle_comment", (KeyCode::Char('/'), KeyModifiers::CONTROL)),
+    ("next_buffer", (KeyCode::PageDown, KeyModifiers::CONTROL)),
+    ("previous_buffer", (KeyCode::PageUp, KeyModifiers::CONTROL)),
+    ("split", (KeyCode::Char('v'), KeyModifiers::ALT)),
+    ("reload", (KeyCode::Char('r'), KeyModifiers::ALT)),
+    ("repeat", (KeyCode::Char('.'), KeyModifiers::ALT)),
+    ("match_bracket", (KeyCode::Char('m'), KeyModifiers::ALT)),
+    ("stats", (KeyCode::Char('w'), KeyModifiers::ALT)),
+    ("command_line", (KeyCode::Char(':'), KeyModifiers::ALT)),
+    ("read_only", (KeyCode::Char('l'), KeyModifiers::ALT)),
+    ("soft_wrap", (KeyCode::Char('z'), KeyModifiers::ALT)),
+    ("suspend", (KeyCode::Char('s'), KeyModifiers::ALT)),
+    ("add_word", (KeyCode::Char('a'), KeyModifiers::ALT)),
+    ("record_macro", (KeyCode::F(7), KeyModifiers::NONE)),
+    ("stop_macro", (KeyCode::F(8), KeyModifiers::NONE)),
+    ("play_macro", (KeyCode::F(9), KeyModifiers::NONE)),
+];
+
+// Reads a key written like "ctrl+f", "alt+shift+up" or "f3".
+// Modifiers come first, the key last
+fn parse_key_chord(chord: &str) -> Option<KeyChord> {
+    let chord = chord.to_lowercase();
+    let (modifier_names, key) = match chord.rsplit_once('+') {
+        // A + on its own is the key, like in "ctrl++"
+        Some((modifier_names, "")) => (modifier_names.strip_suffix('+')?, "+"),
+        Some((modifier_names, key)) => (modifier_names, key),
+        None => ("", chord.as_str()),
+    };
+
+    let mut modifiers = KeyModifiers::NONE;
+    for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+        modifiers |= match name {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+    }
+
+    let mut chars = key.chars();
+    let code = match key {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "tab" => KeyCode::Tab,
+        "enter" => KeyCode::Enter,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "esc" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        _ => match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            (Some('f'), Some(_)) => KeyCode::F(key[1..].parse().ok().filter(|n| *n > 0)?),
+            _ => return None,
+        },
+    };
+    Some((code, modifiers))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self {
+            tab_stop: DEFAULT_TAB_STOP,
+            soft_tabs: false,
+            line_numbers: LineNumbers::Off,
+            auto_close: false,
+            trim_trailing_whitespace: false,
+            trailing_newline: true,
+            line_ending: None,
+            highlight_current_line: false,
+            ruler: Some(DEFAULT_RULER),
+            show_whitespace: false,
+            highlight_trailing_whitespace: false,
+            scrollbar: false,
+            welcome: vec!["Pound editor --- Version {version}".into()],
+            spell_check: false,
+            dictionary: PathBuf::from("/usr/share/dict/words"),
+            status_timeout: Some(DEFAULT_STATUS_TIMEOUT),
+            scrolloff: DEFAULT_SCROLLOFF,
+            scroll_lines: DEFAULT_SCROLL_LINES,
+            remember_position: true,
+            modal: false,
+            bell: Bell::Off,
+            confirm_quit: true,
+            soft_wrap: false,
+            autosave: None,
+            format_on_save: false,
+            formatters: HashMap::from([("rust".into(), "rustfmt --emit stdout".into())]),
+            theme: Theme::new(),
+            color_depth: ColorDepth::detect(),
+            key_bindings: HashMap::new(),
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("pound").join("config.toml"))
+    }
+
+    // Loads the config file. A missing file just means the
+    // defaults are used, a file that can't be read or parsed
+    // also falls back to the defaults but returns the error
+    // so it can be shown in the status message
+    fn load() -> (Self, Option<String>) {
+        let path = match Self::path() {
+            None => return (Self::new(), None),
+            Some(path) => path,
+        };
+
+        match fs::read_to_string(path) {
+            Ok(contents) => match Self::parse(&contents) {
+                Ok((config, warning)) => (config, warning),
+                Err(err) => (Self::new(), Some(format!("Config error: {}", err))),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Self::new(), None),
+            Err(err) => (Self::new(), Some(format!("Config error: {}", err))),
+        }
+    }
+
+    // A bad key binding doesn't stop the rest of the config
+    // from being used, it's returned as a warning instead
+    pub fn parse(contents: &str) -> Result<(Self, Option<String>), String> {
+        let table = contents
+            .parse::<toml::Table>()
+            .map_err(|err| err.to_string().lines().next().unwrap_or_default().to_string())?;
+        let mut config = Self::new();
+        config.apply(&table)?;
+        let warning = config
+            .bind_keys(&table)
+            .map(|err| format!("Config warning: {}", err));
+        Ok((config, warning))
+    }
+
+    // Binds the keys of the [keys] table, which maps action
+    // names to keys like save = "ctrl+w". The default keys
+    // keep working unless something else is bound to them.
+    // Bindings that can't be read are skipped, the first
+    // problem found is returned
+    fn bind_keys(&mut self, table: &toml::Table) -> Option<String> {
+        let keys = match table.get("keys") {
+            Some(toml::Value::Table(keys)) => keys,
+            Some(_) => return Some("keys must be a table of actions and keys".into()),
+            None => return None,
+        };
+
+        let mut warning = None;
+        for (action, chord) in keys {
+            let default = KEY_ACTIONS
+                .iter()
+                .find(|(name, _)| name == action)
+                .map(|(_, default)| *default);
+            let binding = match (default, chord.as_str().and_then(parse_key_chord)) {
+                (None, _) => Err(format!("there's no action called {}", action)),
+                (_, None) => Err(format!("the key for {} isn't one that can be bound", action)),
+                (Some(default), Some(chord)) => Ok((chord, default)),
+            };
+            match binding {
+                Ok((chord, default)) => {
+                    self.key_bindings.insert(chord, default);
+                }
+                Err(err) => {
+                    warning.get_or_insert(err);
+                }
+            }
+        }
+        warning
+    }
+
+    // The key the match in process_keypress is written for
+    // when the one pressed is bound to an action
+    fn translate_key(&self, key_event: KeyEvent) -> KeyEvent {
+        match self.key_bindings.get(&(key_event.code, key_event.modifiers)) {
+            Some(&(code, modifiers)) => KeyEvent {
+                code,
+                modifiers,
+                ..key_event
+            },
+            None => key_event,
+        }
+    }
+
+    // Every option the config file can set
+    const OPTIONS: &'static [&'static str] = &[
+        "tab_stop",
+        "scrolloff",
+        "scroll_lines",
+        "soft_tabs",
+        "auto_close",
+        "trim_trailing_whitespace",
+        "trailing_newline",
+        "highlight_current_line",
+        "show_whitespace",
+        "highlight_trailing_whitespace",
+        "scrollbar",
+        "welcome",
+        "spell_check",
+        "dictionary",
+        "soft_wrap",
+        "format_on_save",
+        "remember_position",
+        "modal",
+        "confirm_quit",
+        "bell",
+        "status_timeout",
+        "autosave",
+        "ruler",
+        "line_ending",
+        "formatters",
+        "theme",
+        "color_depth",
+        "line_numbers",
+    ];
+
+    // Sets the options found in the table, the rest keep
+    // the value they had
+    fn apply(&mut self, table: &toml::Table) -> Result<(), String> {
+        if let Some(value) = table.get("tab_stop") {
+            self.tab_stop = value
+                .as_integer()
+                .filter(|&tab_stop| tab_stop > 0)
+                .ok_or("tab_stop must be a positive integer")? as usize;
+        }
+        if let Some(value) = table.get("scrolloff") {
+            self.scrolloff = value
+                .as_integer()
+                .filter(|&scrolloff| scrolloff >= 0)
+                .ok_or("scrolloff must be a number of rows")? as usize;
+        }
+        if let Some(value) = table.get("scroll_lines") {
+            self.scroll_lines = value
+                .as_integer()
+                .filter(|&scroll_lines| scroll_lines > 0)
+                .ok_or("scroll_lines must be a positive number of rows")?
+                as usize;
+        }
+        Self::parse_bool(table, "soft_tabs", &mut self.soft_tabs)?;
+        Self::parse_bool(table, "auto_close", &mut self.auto_close)?;
+        Self::parse_bool(
+            table,
+            "trim_trailing_whitespace",
+            &mut self.trim_trailing_whitespace,
+        )?;
+        Self::parse_bool(table, "trailing_newline", &mut self.trailing_newline)?;
+        Self::parse_bool(
+            table,
+            "highlight_current_line",
+            &mut self.highlight_current_line,
+        )?;
+        Self::parse_bool(table, "show_whitespace", &mut self.show_whitespace)?;
+        Self::parse_bool(
+            table,
+            "highlight_trailing_whitespace",
+            &mut self.highlight_trailing_whitespace,
+        )?;
+        Self::parse_bool(table, "scrollbar", &mut self.scrollbar)?;
+        Self::parse_bool(table, "soft_wrap", &mut self.soft_wrap)?;
+        Self::parse_bool(table, "spell_check", &mut self.spell_check)?;
+        if let Some(value) = table.get("dictionary") {
+            self.dictionary = value
+                .as_str()
+                .ok_or("dictionary must be the path of a word list")?
+                .into();
+        }
+        Self::parse_bool(table, "format_on_save", &mut self.format_on_save)?;
+        Self::parse_bool(table, "remember_position", &mut self.remember_position)?;
+        Self::parse_bool(table, "modal", &mut self.modal)?;
+        Self::parse_bool(table, "confirm_quit", &mut self.confirm_quit)?;
+        if let Some(value) = table.get("status_timeout") {
+            self.status_timeout = match (value.as_bool(), value.as_integer()) {
+                (Some(false), _) => None,
+                (_, Some(seconds)) if seconds > 0 => Some(Duration::from_secs(seconds as u64)),
+                _ => return Err("status_timeout must be a number of seconds or false".into()),
+            };
+        }
+        if let Some(value) = table.get("autosave") {
+            self.autosave = match (value.as_bool(), value.as_integer()) {
+                (Some(false), _) => None,
+                (_, Some(seconds)) if seconds > 0 => Some(Duration::from_secs(seconds as u64)),
+                _ => return Err("autosave must be a number of seconds or false".into()),
+            };
+        }
+        if let Some(value) = table.get("ruler") {
+            self.ruler = match (value.as_bool(), value.as_integer()) {
+                (Some(false), _) | (_, Some(0)) => None,
+                (_, Some(column)) if column > 0 => Some(column as usize),
+                _ => return Err("ruler must be a column number or false".into()),
+            };
+        }
+        if let Some(value) = table.get("line_ending") {
+            self.line_ending = match value.as_str() {
+                Some("lf") => Some(LineEnding::Lf),
+                Some("crlf") => Some(LineEnding::CrLf),
+                Some("auto") => None,
+                _ => return Err("line_ending must be \"lf\", \"crlf\" or \"auto\"".into()),
+            };
+        }
+        if let Some(value) = table.get("welcome") {
+            self.welcome = match value {
+                toml::Value::Boolean(false) => Vec::new(),
+                toml::Value::String(line) => vec![line.clone()],
+                toml::Value::Array(lines) => lines
+                    .iter()
+                    .map(|line| line.as_str().map(String::from))
+                    .collect::<Option<_>>()
+                    .ok_or("welcome lines must be strings")?,
+                _ => return Err("welcome must be a list of lines or false".into()),
+            };
+        }
+        if let Some(value) = table.get("formatters") {
+            let formatters = value
+                .as_table()
+                .ok_or("formatters must be a table of file types and commands")?;
+            for (file_type, command) in formatters {
+                let command = command
+                    .as_str()
+                    .ok_or_else(|| format!("the formatter for {} must be a string", file_type))?;
+                self.formatters.insert(file_type.clone(), command.into());
+            }
+        }
+        if let Some(value) = table.get("theme") {
+            let theme = value
+                .as_table()
+                .ok_or("theme must be a table of colors")?;
+            self.theme.apply(theme)?;
+        }
+        if let Some(value) = table.get("color_depth") {
+            self.color_depth = ColorDepth::parse(value)
+                .ok_or("color_depth must be 16, 256, \"truecolor\" or \"auto\"")?
+                .unwrap_or_else(ColorDepth::detect);
+        }
+        if let Some(value) = table.get("bell") {
+            self.bell = match (value.as_bool(), value.as_str()) {
+                (Some(false), _) | (_, Some("off")) => Bell::Off,
+                (Some(true), _) | (_, Some("visual")) => Bell::Visual,
+                (_, Some("audible")) => Bell::Audible,
+                _ => return Err("bell must be \"off\", \"visual\" or \"audible\"".into()),
+            };
+        }
+        if let Some(value) = table.get("line_numbers") {
+            self.line_numbers = match (value.as_bool(), value.as_str()) {
+                (Some(true), _) | (_, Some("absolute")) => LineNumbers::Absolute,
+                (Some(false), _) | (_, Some("off")) => LineNumbers::Off,
+                (_, Some("relative")) => LineNumbers::Relative,
+                _ => return Err("line_numbers must be \"absolute\", \"relative\" or \"off\"".into()),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn parse_bool(table: &toml::Table, key: &str, value: &mut bool) -> Result<(), String> {
+        if let Some(entry) = table.get(key) {
+            *value = entry
+                .as_bool()
+                .ok_or_else(|| format!("{} must be true or false", key))?;
+        }
+        Ok(())
+    }
+}
+
+// Where the cursor was left in each file, kept in
+// ~/.local/state/pound/positions as lines of "row column
+// path", the most recently left file last. It only makes
+// reopening a file nicer, so a missing or broken file is
+// treated as empty and failing to write it is ignored
+struct CursorPositions {
+    entries: Vec<(PathBuf, (usize, usize))>,
+}
+
+// Only the files left most recently are remembered
+const POSITIONS_KEPT: usize = 500;
+
+impl CursorPositions {
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })
+            .map(|dir| dir.join("pound").join("positions"))
+    }
+
+    fn load() -> Self {
+        let contents = Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ' ');
+                let y = fields.next()?.parse().ok()?;
+                let x = fields.next()?.parse().ok()?;
+                Some((PathBuf::from(fields.next()?), (x, y)))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    // Files are looked up by their absolute path so it
+    // doesn't matter which directory they're opened from
+    fn key(filename: &Path) -> PathBuf {
+        fs::canonicalize(filename).unwrap_or_else(|_| filename.to_path_buf())
+    }
+
+    fn get(&self, filename: &Path) -> Option<(usize, usize)> {
+        let key = Self::key(filename);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(path, _)| *path == key)
+            .map(|(_, position)| *position)
+    }
+
+    fn set(&mut self, filename: &Path, position: (usize, usize)) {
+        let key = Self::key(filename);
+        self.entries.retain(|(path, _)| *path != key);
+        self.entries.push((key, position));
+        let excess = self.entries.len().saturating_sub(POSITIONS_KEPT);
+        self.entries.drain(..excess);
+    }
+
+    fn store(&self) {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return,
+        };
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|(file, (x, y))| format!("{} {} {}\n", y, x, file.display()))
+            .collect();
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, contents);
+    }
+}
+
+// Used to move around the cursor based on
+// some user key presses
+#[derive(Clone, Copy)]
+pub struct CursorController {
+    cursor_x: usize,
+    cursor_y: usize,
+    screen_columns: usize,
+    screen_rows: usize,
+    row_offset: usize,
+    column_offset: usize,
+    render_x: usize,
+    // Long rows continue on the next screen rows instead
+    // of scrolling horizontally
+    soft_wrap: bool,
+}
+
+impl CursorController {
+    pub fn new(win_size: (usize, usize)) -> CursorController {
+        Self {
+            cursor_x: 0,
+            cursor_y: 0,
+            screen_columns: win_size.0,
+            screen_rows: win_size.1,
+            row_offset: 0,
+            column_offset: 0,
+            render_x: 0,
+            soft_wrap: false,
+        }
+    }
+
+    fn get_render_x(&self, row: &Row, tab_stop: usize) -> usize {
+        row.get_render_x(self.cursor_x, tab_stop)
+    }
+
+    // Character column and row of the cursor in the file
+    pub fn position(&self) -> (usize, usize) {
+        (self.cursor_x, self.cursor_y)
+    }
+
+    pub fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+
+        match direction {
+            KeyCode::Up | KeyCode::Down if self.soft_wrap => {
+                self.move_visual_row(direction, editor_rows)
+            }
+            KeyCode::Up => {
+                self.cursor_y = self.cursor_y.saturating_sub(1);
+            }
+            KeyCode::Left => {
+                if self.cursor_x != 0 {
+                    self.cursor_x = editor_rows
+                        .get_editor_row(self.cursor_y)
+                        .previous_boundary(self.cursor_x);
+                } else if self.cursor_y > 0 {
+                    self.cursor_y -= 1;
+                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
+                }
+            }
+            KeyCode::Down => {
+                if self.cursor_y < number_of_rows {
+                    self.cursor_y += 1;
+                }
+            }
+            KeyCode::Right => {
+                if self.cursor_y < number_of_rows {
+                    let row = editor_rows.get_editor_row(self.cursor_y);
+                    if self.cursor_x < row.len() {
+                        self.cursor_x = row.next_boundary(self.cursor_x);
+                    } else {
+                        self.cursor_x = 0;
+                        self.cursor_y += 1;
+                    }
+                }
+            }
+            // Home goes to the first non-blank character, or to
+            // the start of the row when it's already there
+            KeyCode::Home => {
+                let indentation = if self.cursor_y < number_of_rows {
+                    let row = &editor_rows.get_editor_row(self.cursor_y).row_content;
+                    row.chars().take_while(|ch| ch.is_whitespace()).count()
+                } else {
+                    0
+                };
+                self.cursor_x = if self.cursor_x == indentation { 0 } else { indentation };
+            }
+            KeyCode::End => {
+                if self.cursor_y < number_of_rows {
+                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
+                }
+            }
+            _ => unimplemented!(),
+        }
+
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    // Render columns the visual rows of file row y start at,
+    // the row past the end of the file is a single visual row
+    fn wrap_starts(&self, editor_rows: &EditorRows, y: usize) -> Vec<usize> {
+        if y < editor_rows.number_of_rows() {
+            editor_rows.get_editor_row(y).wrap_starts(self.screen_columns)
+        } else {
+            vec![0]
+        }
+    }
+
+    // Index of the visual row render column x is on
+    fn wrap_segment(starts: &[usize], render_x: usize) -> usize {
+        starts
+            .iter()
+            .rposition(|&start| start <= render_x)
+            .unwrap_or(0)
+    }
+
+    // With soft wrap on Up and Down move a visual row at a time,
+    // keeping the column within the visual row where it fits
+    fn move_visual_row(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+        let render_x = if self.cursor_y < number_of_rows {
+            self.get_render_x(editor_rows.get_editor_row(self.cursor_y), editor_rows.tab_stop)
+        } else {
+            0
+        };
+        let starts = self.wrap_starts(editor_rows, self.cursor_y);
+        let segment = Self::wrap_segment(&starts, render_x);
+        let column = render_x - starts[segment];
+
+        let (y, segment) = match direction {
+            KeyCode::Up if segment > 0 => (self.cursor_y, segment - 1),
+            KeyCode::Up if self.cursor_y > 0 => (
+                self.cursor_y - 1,
+                self.wrap_starts(editor_rows, self.cursor_y - 1).len() - 1,
+            ),
+            KeyCode::Down if segment + 1 < starts.len() => (self.cursor_y, segment + 1),
+            KeyCode::Down if self.cursor_y < number_of_rows => (self.cursor_y + 1, 0),
+            _ => return,
+        };
+        self.cursor_y = y;
+        if y >= number_of_rows {
+            return;
+        }
+
+        let row = editor_rows.get_editor_row(y);
+        let starts = self.wrap_starts(editor_rows, y);
+        let mut target = starts[segment] + column;
+        if let Some(next_start) = starts.get(segment + 1) {
+            target = cmp::min(target, next_start - 1);
+        }
+        self.cursor_x = if target >= row.render_width() {
+            row.len()
+        } else {
+            row.get_row_content_x(target, editor_rows.tab_stop)
+        };
+        // A tab split over two visual rows starts on the earlier
+        // one, so the cursor goes past it to actually move down
+        let render_x = row.get_render_x(self.cursor_x, editor_rows.tab_stop);
+        if Self::wrap_segment(&starts, render_x) < segment {
+            self.cursor_x += 1;
+        }
+    }
+
+    // Screen position of the cursor within the text area
+    pub fn screen_position(&self, editor_rows: &EditorRows) -> (usize, usize) {
+        if !self.soft_wrap {
+            return (
+                self.render_x - self.column_offset,
+                self.cursor_y - self.row_offset,
+            );
+        }
+
+        let starts = self.wrap_starts(editor_rows, self.cursor_y);
+        let segment = Self::wrap_segment(&starts, self.render_x);
+        let rows_above: usize = (self.row_offset..self.cursor_y)
+            .map(|y| self.wrap_starts(editor_rows, y).len())
+            .sum();
+        (self.render_x - starts[segment], rows_above + segment)
+    }
+
+    // Scrolls the view by rows without moving it past the start
+    // or end of the file, the cursor is moved along only as far
+    // as it takes to stay on screen
+    fn scroll_view(&mut self, up: bool, rows: usize, editor_rows: &EditorRows, scrolloff: usize) {
+        let max_offset = (editor_rows.number_of_rows() + 1).saturating_sub(self.screen_rows);
+        self.row_offset = if up {
+            self.row_offset.saturating_sub(rows)
+        } else {
+            cmp::min(self.row_offset + rows, max_offset)
+        };
+
+        let scrolloff = cmp::min(scrolloff, self.screen_rows.saturating_sub(1) / 2);
+        let top = if self.row_offset > 0 {
+            self.row_offset + scrolloff
+        } else {
+            0
+        };
+        // The last row that fits above the bottom scrolloff rows,
+        // wrapped rows taking up more than one screen row
+        let mut bottom = self.row_offset;
+        let mut used = 0;
+        while bottom < editor_rows.number_of_rows() {
+            used += if self.soft_wrap {
+                self.wrap_starts(editor_rows, bottom).len()
+            } else {
+                1
+            };
+            if used > self.screen_rows.saturating_sub(scrolloff) {
+                break;
+            }
+            bottom += 1;
+        }
+        let bottom = if bottom < editor_rows.number_of_rows() {
+            bottom.saturating_sub(1)
+        } else {
+            bottom
+        };
+        self.cursor_y = self.cursor_y.clamp(top, cmp::max(top, bottom));
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    // Moves the cursor to where a click at (column, row) of the
+    // text area landed, or the end of the line when past it
+    fn click(&mut self, column: usize, row: usize, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+        let (y, render_x) = if self.soft_wrap {
+            let (mut y, mut row) = (self.row_offset, row);
+            loop {
+                let starts = self.wrap_starts(editor_rows, y);
+                if row < starts.len() || y >= number_of_rows {
+                    let segment = cmp::min(row, starts.len() - 1);
+                    let mut render_x = starts[segment] + column;
+                    if let Some(next_start) = starts.get(segment + 1) {
+                        render_x = cmp::min(render_x, next_start - 1);
+                    }
+                    break (y, render_x);
+                }
+                row -= starts.len();
+                y += 1;
+            }
+        } else {
+            (
+                cmp::min(self.row_offset + row, number_of_rows),
+                self.column_offset + column,
+            )
+        };
+
+        self.cursor_y = y;
+        self.cursor_x = if y < number_of_rows {
+            let row = editor_rows.get_editor_row(y);
+            if render_x >= row.render_width() {
+                row.len()
+            } else {
+                row.get_row_content_x(render_x, editor_rows.tab_stop)
+            }
+        } else {
+            0
+        };
+    }
+
+    // Moves the cursor and the view a full screen of rows
+    // up or down, keeping cursor_x within the new row
+    fn move_page(&mut self, down: bool, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+
+        if down {
+            let max_offset = (number_of_rows + 1).saturating_sub(self.screen_rows);
+            self.cursor_y = cmp::min(self.cursor_y + self.screen_rows, number_of_rows);
+            self.row_offset = cmp::min(self.row_offset + self.screen_rows, max_offset);
+        } else {
+            self.cursor_y = self.cursor_y.saturating_sub(self.screen_rows);
+            self.row_offset = self.row_offset.saturating_sub(self.screen_rows);
+        }
+
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    // Ctrl+Home goes to the start of the file and Ctrl+End
+    // to the end of its last line. The view scrolls to the
+    // cursor on the next refresh like it does for PageUp/Down
+    fn move_to_file_edge(&mut self, end: bool, editor_rows: &EditorRows) {
+        if end {
+            self.cursor_y = editor_rows.number_of_rows().saturating_sub(1);
+            self.cursor_x = usize::MAX;
+        } else {
+            (self.cursor_x, self.cursor_y) = (0, 0);
+        }
+
+        self.clamp_cursor_x(editor_rows);
+    }
+
+    // Used by Ctrl+Left/Right to jump a word at a time,
+    // wrapping to the adjacent line at the start or end
+    // of a row like the arrow keys do
+    fn move_word(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
+        if self.cursor_y >= editor_rows.number_of_rows() {
+            self.move_cursor(direction, editor_rows);
+            return;
+        }
+
+        let row = editor_rows.get_editor_row(self.cursor_y);
+        match direction {
+            KeyCode::Left if self.cursor_x > 0 => {
+                self.cursor_x = row.previous_word_start(self.cursor_x)
+            }
+            KeyCode::Right if self.cursor_x < row.len() => {
+                self.cursor_x = row.next_word_end(self.cursor_x)
+            }
+            _ => self.move_cursor(direction, editor_rows),
+        }
+    }
+
+    // Moves to the bracket matching the one under the cursor,
+    // counting nested pairs of the same kind on the way.
+    // Returns false if the cursor isn't on a bracket or it
+    // has no match, leaving the cursor where it is
+    fn match_bracket(&mut self, editor_rows: &EditorRows) -> bool {
+        if self.cursor_y >= editor_rows.number_of_rows() {
+            return false;
+        }
+        let row = editor_rows.get_editor_row(self.cursor_y);
+        let (open, close, forward) = match row.row_content.chars().nth(self.cursor_x) {
+            Some('(') => ('(', ')', true),
+            Some('[') => ('[', ']', true),
+            Some('{') => ('{', '}', true),
+            Some(')') => ('(', ')', false),
+            Some(']') => ('[', ']', false),
+            Some('}') => ('{', '}', false),
+            _ => return false,
+        };
+
+        // The bracket under the cursor is scanned first, so
+        // depth drops back to 0 on its match
+        let mut depth = 0;
+        if forward {
+            for y in self.cursor_y..editor_rows.number_of_rows() {
+                let skip = if y == self.cursor_y { self.cursor_x } else { 0 };
+                let chars = editor_rows.get_editor_row(y).row_content.chars();
+                for (x, c) in chars.enumerate().skip(skip) {
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            (self.cursor_x, self.cursor_y) = (x, y);
+                            return true;
+                        }
+                    }
+                }
+            }
+        } else {
+            for y in (0..=self.cursor_y).rev() {
+                let chars: Vec<char> = editor_rows.get_editor_row(y).row_content.chars().collect();
+                let end = if y == self.cursor_y { self.cursor_x + 1 } else { chars.len() };
+                for x in (0..end).rev() {
+                    if chars[x] == close {
+                        depth += 1;
+                    } else if chars[x] == open {
+                        depth -= 1;
+                        if depth == 0 {
+                            (self.cursor_x, self.cursor_y) = (x, y);
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // Keeps cursor_x within the row the cursor is on
+    // after cursor_y has been changed
+    fn clamp_cursor_x(&mut self, editor_rows: &EditorRows) {
+        let row_len = if self.cursor_y < editor_rows.number_of_rows() {
+            editor_rows.get_editor_row(self.cursor_y).len()
+        } else {
+            0
+        };
+
+        self.cursor_x = cmp::min(self.cursor_x, row_len);
+    }
+
+    // Scrolls so the cursor row ends up at the given row of
+    // the screen, without scrolling past the end of the file
+    fn scroll_cursor_to(&mut self, screen_row: usize, editor_rows: &EditorRows) {
+        let max_offset = (editor_rows.number_of_rows() + 1).saturating_sub(self.screen_rows);
+        self.row_offset = cmp::min(self.cursor_y.saturating_sub(screen_row), max_offset);
+    }
+
+    fn center_view(&mut self, editor_rows: &EditorRows) {
+        self.scroll_cursor_to(self.screen_rows / 2, editor_rows);
+    }
+
+    // Scrolls so the cursor is on screen with at least
+    // scrolloff rows above and below it, unless that would
+    // go past the start or end of the file
+    fn scroll(&mut self, editor_rows: &EditorRows, scrolloff: usize) {
+        self.render_x = 0;
+        // Render column just past the character under the
+        // cursor, a tab there can be up to tab_stop wide
+        let mut character_end = 1;
+        if self.cursor_y < editor_rows.number_of_rows() {
+            let row = editor_rows.get_editor_row(self.cursor_y);
+            self.render_x = self.get_render_x(row, editor_rows.tab_stop);
+            character_end = cmp::max(
+                row.get_render_x(self.cursor_x + 1, editor_rows.tab_stop),
+                self.render_x + 1,
+            );
+        }
+
+        // vertical scroll
+        let scrolloff = cmp::min(scrolloff, self.screen_rows.saturating_sub(1) / 2);
+        if self.soft_wrap {
+            self.scroll_wrapped(editor_rows, scrolloff);
+            return;
+        }
+        let rows_below = cmp::min(
+            scrolloff,
+            editor_rows.number_of_rows().saturating_sub(self.cursor_y),
+        );
+        self.row_offset = cmp::min(self.row_offset, self.cursor_y.saturating_sub(scrolloff));
+        if self.cursor_y + rows_below >= self.row_offset + self.screen_rows {
+            // Without any text rows on screen this would put the
+            // offset past the cursor, so it's capped at cursor_y
+            self.row_offset = cmp::min(
+                (self.cursor_y + rows_below + 1).saturating_sub(self.screen_rows),
+                self.cursor_y,
+            );
+        }
+
+        // horizontal scroll, the whole character under the cursor
+        // is brought on screen so moving onto a tab at the right
+        // edge doesn't leave most of it hidden, but render_x always
+        // stays within column_offset..column_offset + screen_columns
+        self.column_offset = cmp::min(self.column_offset, self.render_x);
+        if character_end > self.column_offset + self.screen_columns {
+            self.column_offset = cmp::min(
+                character_end.saturating_sub(self.screen_columns),
+                self.render_x,
+            );
+        }
+    }
+
+    // Vertical scroll counted in visual rows. The view still
+    // starts at the beginning of a file row, and nothing is
+    // scrolled horizontally since every row fits the width
+    fn scroll_wrapped(&mut self, editor_rows: &EditorRows, scrolloff: usize) {
+        self.column_offset = 0;
+        let number_of_rows = editor_rows.number_of_rows();
+        let starts = self.wrap_starts(editor_rows, self.cursor_y);
+        let segment = Self::wrap_segment(&starts, self.render_x);
+
+        let mut rows_below = starts.len() - 1 - segment;
+        let mut y = self.cursor_y + 1;
+        while rows_below < scrolloff && y <= number_of_rows {
+            rows_below += self.wrap_starts(editor_rows, y).len();
+            y += 1;
+        }
+        let rows_below = cmp::min(rows_below, scrolloff);
+
+        self.row_offset = cmp::min(self.row_offset, self.cursor_y.saturating_sub(scrolloff));
+        let mut height: usize = (self.row_offset..self.cursor_y)
+            .map(|y| self.wrap_starts(editor_rows, y).len())
+            .sum::<usize>()
+            + segment
+            + 1
+            + rows_below;
+        while height > self.screen_rows && self.row_offset < self.cursor_y {
+            height -= self.wrap_starts(editor_rows, self.row_offset).len();
+            self.row_offset += 1;
+        }
+    }
+}
+
+struct StatusMessage {
+    message: Option<String>,
+    set_time: Option<Instant>,
+    // How long the current message is shown for,
+    // None keeps it until it's replaced
+    timeout: Option<Duration>,
+    default_timeout: Option<Duration>,
+}
+
+impl StatusMessage {
+    fn new(initial_message: String, default_timeout: Option<Duration>) -> Self {
+        Self {
+            message: Some(initial_message),
+            set_time: Some(Instant::now()),
+            timeout: default_timeout,
+            default_timeout,
+        }
+    }
+
+    fn set_message(&mut self, message: String) {
+        self.set_message_for(message, self.default_timeout)
+    }
+
+    fn set_message_for(&mut self, message: String, timeout: Option<Duration>) {
+        self.message = Some(message);
+        self.set_time = Some(Instant::now());
+        self.timeout = timeout;
+    }
+
+    fn message(&mut self) -> Option<&String> {
+        let timeout = self.timeout;
+        self.set_time.and_then(|time| {
+            if timeout.is_some_and(|timeout| time.elapsed() > timeout) {
+                self.message = None;
+                self.set_time = None;
+                None
+            } else {
+                Some(self.message.as_ref().unwrap())
+            }
+        })
+    }
+}
+
+enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+struct SearchIndex {
+    x_index: usize,
+    y_index: usize,
+    x_direction: Option<SearchDirection>,
+    y_direction: Option<SearchDirection>,
+    // Whether the find prompt is currently open
+    active: bool,
+    keyword: String,
+    // Row and render column of the match the cursor is on
+    current_match: Option<(usize, usize)>,
+    // Kept between searches, toggled with Ctrl+I
+    case_insensitive: bool,
+    // Row and render column of every match, found again only
+    // when the keyword or case_insensitive it was counted for
+    // changes and not when moving between the matches
+    matches: Vec<(usize, usize)>,
+    counted: Option<(String, bool)>,
+    // The keyword of the last search confirmed with Enter,
+    // F3 and Shift+F3 look for it again
+    confirmed: String,
+}
+
+impl SearchIndex {
+    fn new() -> Self {
+        Self {
+            x_index: 0,
+            y_index: 0,
+            x_direction: None,
+            y_direction: None,
+            active: false,
+            keyword: String::new(),
+            current_match: None,
+            case_insensitive: false,
+            matches: Vec::new(),
+            counted: None,
+            confirmed: String::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x_index = 0;
+        self.y_index = 0;
+        self.x_direction = None;
+        self.y_direction = None;
+        self.keyword.clear();
+        self.current_match = None;
+        self.matches.clear();
+        self.counted = None;
+    }
+
+    // "Match 3 of 12" for the status bar while finding
+    fn match_count(&self) -> Option<String> {
+        let (keyword, _) = self.counted.as_ref()?;
+        if keyword.is_empty() {
+            return None;
+        }
+        let current = self
+            .current_match
+            .and_then(|current| self.matches.iter().position(|&found| found == current));
+        Some(match (current, self.matches.len()) {
+            (_, 0) => "No matches".to_string(),
+            (Some(index), total) => format!("Match {} of {}", index + 1, total),
+            (None, total) => format!("{} matches", total),
+        })
+    }
+}
+
+// A range of selected text. The anchor is where the
+// selection was started and active is the end that
+// moves with the cursor, both are (x, y) positions
+#[derive(Clone, Copy)]
+struct Selection {
+    anchor: (usize, usize),
+    active: (usize, usize),
+}
+
+impl Selection {
+    // Returns the two ends of the selection, the one that
+    // comes first in the file first
+    fn bounds(&self) -> ((usize, usize), (usize, usize)) {
+        let (anchor, active) = (self.anchor, self.active);
+        if (anchor.1, anchor.0) <= (active.1, active.0) {
+            (anchor, active)
+        } else {
+            (active, anchor)
+        }
+    }
+
+    // Start and end cursor_x of the selected part of row y
+    fn row_range(&self, y: usize, row: &Row) -> Option<(usize, usize)> {
+        let (start, end) = self.bounds();
+        if y < start.1 || y > end.1 {
+            return None;
+        }
+
+        let start_x = if y == start.1 { start.0 } else { 0 };
+        let end_x = if y == end.1 { end.0 } else { row.len() };
+        Some((cmp::min(start_x, row.len()), cmp::min(end_x, row.len())))
+    }
+}
+
+// A single change made to the editor rows. Each operation
+// knows how to be applied again (redo) and how to be
+// reversed (undo)
+#[derive(Clone)]
+enum EditOperation {
+    InsertText { x: usize, y: usize, text: String },
+    DeleteText { x: usize, y: usize, text: String },
+    // Splits row y at x (or adds an empty row if y is the
+    // last, empty line)
+    InsertNewline { x: usize, y: usize },
+    // Appends row y + 1 to the end of row y, x is the length
+    // of row y before the join
+    JoinRows { x: usize, y: usize },
+    InsertRow { y: usize, text: String },
+    // Swaps row y with row y + 1
+    SwapRows { y: usize },
+    RemoveRow { y: usize, text: String },
+}
+
+// One undo unit, made up of the operations it performed
+// and where the cursor was before and after them
+struct UndoEntry {
+    operations: Vec<EditOperation>,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+}
+
+// Keeps track of the edits done to the rows so they can be
+// undone with Ctrl+Z and redone with Ctrl+Y
+struct EditHistory {
+    undo_stack: Vec<UndoEntry>,
+    redo_stack: Vec<UndoEntry>,
+    // Length of the undo stack when the file was last
+    // saved, None if that state can't be reached anymore
+    saved_len: Option<usize>,
+    // Whether the next single character insert may be
+    // merged with the previous one
+    coalesce: bool,
+}
+
+impl EditHistory {
+    fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            saved_len: Some(0),
+            coalesce: false,
+        }
+    }
+
+    fn push(&mut self, entry: UndoEntry) {
+        self.redo_stack.clear();
+        if matches!(self.saved_len, Some(len) if len > self.undo_stack.len()) {
+            self.saved_len = None;
+        }
+
+        self.undo_stack.push(entry);
+        self.coalesce = false;
+    }
+
+    fn push_insert_char(&mut self, entry: UndoEntry) {
+        // Consecutive characters typed next to each other are
+        // merged into one entry so undo removes the whole run
+        if self.coalesce && self.redo_stack.is_empty() {
+            if let Some(last) = self.undo_stack.last_mut() {
+                if let (
+                    Some(EditOperation::InsertText { x, y, text }),
+                    [EditOperation::InsertText {
+                        x: new_x,
+                        y: new_y,
+                        text: new_text,
+                    }],
+                ) = (last.operations.last_mut(), entry.operations.as_slice())
+                {
+                    if *y == *new_y
+                        && *x + text.chars().count() == *new_x
+                        && last.cursor_after == entry.cursor_before
+                    {
+                        text.push_str(new_text);
+                        last.cursor_after = entry.cursor_after;
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.push(entry);
+        self.coalesce = true;
+    }
+
+    // Adds an operation to the most recent entry, for edits
+    // that are built on top of another one
+    fn extend_last(&mut self, operation: EditOperation, cursor_after: (usize, usize)) {
+        if let Some(entry) = self.undo_stack.last_mut() {
+            entry.operations.push(operation);
+            entry.cursor_after = cursor_after;
+        }
+    }
+
+    // Merges the entries pushed since the undo stack had len
+    // entries into one, so they are undone together
+    fn merge_since(&mut self, len: usize) {
+        if self.undo_stack.len() <= len + 1 {
+            return;
+        }
+        let mut entries = self.undo_stack.split_off(len).into_iter();
+        if let Some(mut merged) = entries.next() {
+            for entry in entries {
+                merged.operations.extend(entry.operations);
+                merged.cursor_after = entry.cursor_after;
+            }
+            self.undo_stack.push(merged);
+        }
+    }
+
+    fn mark_saved(&mut self) {
+        self.saved_len = Some(self.undo_stack.len());
+        self.coalesce = false;
+    }
+
+    fn is_saved(&self) -> bool {
+        self.saved_len == Some(self.undo_stack.len())
+    }
+
+    // For text that differs from the file however far it's
+    // undone, like unsaved changes brought back by a recovery
+    fn mark_unsaved(&mut self) {
+        self.saved_len = None;
+    }
+}
+
+// An edit made from the keyboard that Alt+. can repeat
+#[derive(Clone)]
+enum RepeatableEdit {
+    // Characters typed one after another, tabs included
+    Insert(String),
+    // Backspace or Delete
+    Delete(KeyCode),
+    Newline,
+    DuplicateLine,
+    DeleteLine,
+    JoinLines,
+}
+
+impl RepeatableEdit {
+    // The edit a key press makes, movement and everything
+    // else that doesn't change the text has none
+    fn for_key(key_event: KeyEvent) -> Option<Self> {
+        match key_event {
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => Some(RepeatableEdit::Insert(ch.into())),
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => Some(RepeatableEdit::Insert("\t".into())),
+            KeyEvent {
+                code: key @ (KeyCode::Backspace | KeyCode::Delete),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => Some(RepeatableEdit::Delete(key)),
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => Some(RepeatableEdit::Newline),
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => Some(RepeatableEdit::DuplicateLine),
+            KeyEvent {
+                code: KeyCode::Char('k'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => Some(RepeatableEdit::DeleteLine),
+            KeyEvent {
+                code: KeyCode::Char('j'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => Some(RepeatableEdit::JoinLines),
+            _ => None,
+        }
+    }
+}
+
+// Output struct is used to handle the output to the
+// terminal screen. This includes the ~ at the start of
+// each line like Vim and also used to ensure that
+// instead of multiple small writes happening each time,
+// one big write happens (efficient)
+pub struct Output {
+    // Size of the text area, without the two bars below it
+    win_size: (usize, usize),
+    terminal_size: (usize, usize),
+    // Which part of the terminal the buffer is drawn in and
+    // the column that part starts at
+    pane: Pane,
+    x_offset: usize,
+    editor_contents: EditorContents,
+    cursor_controller: CursorController,
+    editor_rows: EditorRows,
+    status_message: StatusMessage,
+    // Which of the editor's buffers this is (1 based) and
+    // how many there are, for the status bar
+    buffer_position: (usize, usize),
+    dirty: u64,
+    search_index: SearchIndex,
+    edit_history: EditHistory,
+    config: Config,
+    selection: Option<Selection>,
+    // Positions of closing characters that were inserted
+    // by auto close, these get typed over instead of
+    // being doubled
+    auto_closed: Vec<(usize, usize)>,
+    // The recovery file written for this buffer and the value
+    // of dirty it was written at
+    swap: Option<(PathBuf, u64)>,
+    // Change to the file on disk the user was last told about
+    disk_change_shown: Option<SystemTime>,
+    // The last edit made from the keyboard and where it left
+    // the cursor
+    last_edit: Option<(RepeatableEdit, (usize, usize))>,
+    // Set by -R or Alt+L, edits and saves are refused
+    read_only: bool,
+    // Column of the message bar the cursor is drawn at while
+    // a prompt is being typed into
+    prompt_cursor: Option<usize>,
+    // With modal editing on in the config, whether keys
+    // are commands or typed into the buffer
+    mode: Mode,
+    // When the visual bell last flashed the status bar
+    flashed_at: Option<Instant>,
+}
+
+impl Output {
+    // Instantiates the output for a buffer with the size
+    // of the terminal screen (columns, rows) and the
+    // contents that will go there
+    pub fn new(
+        editor_rows: EditorRows,
+        config: Config,
+        message: String,
+        terminal_size: (u16, u16),
+    ) -> Self {
+        let win_size = Self::text_area_size(terminal_size.0, terminal_size.1);
+        let mode = if config.modal { Mode::Normal } else { Mode::Insert };
+        let mut output = Self {
+            win_size,
+            terminal_size: (terminal_size.0 as usize, terminal_size.1 as usize),
+            pane: Pane::Full,
+            x_offset: 0,
+            editor_contents: EditorContents::new(),
+            cursor_controller: CursorController::new(win_size),
+            editor_rows,
+            status_message: StatusMessage::new(message, config.status_timeout),
+            buffer_position: (1, 1),
+            dirty: 0,
+            search_index: SearchIndex::new(),
+            edit_history: EditHistory::new(),
+            config,
+            selection: None,
+            auto_closed: Vec::new(),
+            swap: None,
+            disk_change_shown: None,
+            last_edit: None,
+            read_only: false,
+            prompt_cursor: None,
+            mode,
+            flashed_at: None,
+        };
+        output.restore_position();
+        output
+    }
+
+    pub fn editor_rows(&self) -> &EditorRows {
+        &self.editor_rows
+    }
+
+    pub fn cursor(&self) -> &CursorController {
+        &self.cursor_controller
+    }
+
+    // Whether there are changes that haven't been saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty > 0
+    }
+
+    // The message last put in the message bar, even if it has
+    // timed out since
+    pub fn status_message(&self) -> Option<&str> {
+        self.status_message.message.as_deref()
+    }
+
+    // Puts the cursor back where it was left in the file
+    fn restore_position(&mut self) {
+        let filename = match &self.editor_rows.filename {
+            Some(filename) if self.config.remember_position => filename,
+            _ => return,
+        };
+        if let Some(position) = CursorPositions::load().get(filename) {
+            self.place_cursor(position);
+        }
+    }
+
+    // Moves the cursor to x, y and scrolls it to the middle of
+    // the screen. It goes as close as it gets for a position
+    // past the end of the row or the file
+    fn place_cursor(&mut self, (x, y): (usize, usize)) {
+        if self.editor_rows.number_of_rows() == 0 {
+            return;
+        }
+        let cursor = &mut self.cursor_controller;
+        cursor.cursor_y = cmp::min(y, self.editor_rows.number_of_rows() - 1);
+        let row = self.editor_rows.get_editor_row(cursor.cursor_y);
+        cursor.cursor_x = row.previous_boundary(cmp::min(x, row.len()) + 1);
+        cursor.center_view(&self.editor_rows);
+    }
+
+    // Records where the cursor is for the next time the
+    // file is opened
+    fn remember_position(&self) {
+        if !self.config.remember_position {
+            return;
+        }
+        if let Some(filename) = &self.editor_rows.filename {
+            let mut positions = CursorPositions::load();
+            positions.set(
+                filename,
+                (
+                    self.cursor_controller.cursor_x,
+                    self.cursor_controller.cursor_y,
+                ),
+            );
+            positions.store();
+        }
+    }
+
+    // The bottom two rows of the terminal are taken
+    // by the status bar and the message bar
+    fn text_area_size(columns: u16, rows: u16) -> (usize, usize) {
+        (columns as usize, (rows as usize).saturating_sub(2))
+    }
+
+    fn resize(&mut self, columns: u16, rows: u16) {
+        let (columns, rows) = (columns as usize, rows as usize);
+        // In a split the left pane is followed by a one
+        // column divider
+        let left_width = columns.saturating_sub(1) / 2;
+        let (x_offset, width) = match self.pane {
+            Pane::Full => (0, columns),
+            Pane::Left => (0, left_width),
+            Pane::Right => (left_width + 1, columns.saturating_sub(left_width + 1)),
+        };
+
+        self.terminal_size = (columns, rows);
+        self.x_offset = x_offset;
+        self.win_size = (width, Self::text_area_size(columns as u16, rows as u16).1);
+        self.cursor_controller.screen_columns = self.win_size.0;
+        self.cursor_controller.screen_rows = self.win_size.1;
+
+        // Whatever was drawn for the old size is cleared on
+        // the next refresh. Panes of a split redraw every cell
+        // of their part, clearing would wipe the other pane
+        if self.pane == Pane::Full {
+            self.editor_contents.clear_screen();
+        } else {
+            self.editor_contents.forget_screen();
+        }
+    }
+
+    fn set_pane(&mut self, pane: Pane) {
+        self.pane = pane;
+        self.resize(self.terminal_size.0 as u16, self.terminal_size.1 as u16);
+    }
+
+    // The pane reaches the right edge of the terminal, so
+    // rows can be cleared to the end of the line
+    fn reaches_right_edge(&self) -> bool {
+        self.x_offset + self.win_size.0 >= self.terminal_size.0
+    }
+
+    // Waits for the next key press, paste or mouse event,
+    // redrawing the screen for the new size whenever the
+    // terminal is resized and autosaving when it has been
+    // idle long enough
+    fn read_input(&mut self, reader: &mut Reader) -> io::Result<Event> {
+        // Undoing back to the saved state leaves nothing to recover
+        if self.dirty == 0 {
+            self.remove_swap();
+        }
+
+        let idle_since = Instant::now();
+        loop {
+            let idle = idle_since.elapsed();
+            // A flashing status bar is drawn again once the
+            // flash is over
+            let flash = self
+                .flashed_at
+                .and_then(|at| BELL_FLASH.checked_sub(at.elapsed()));
+            let timeout = self
+                .idle_timeouts()
+                .into_iter()
+                .flatten()
+                .filter(|&timeout| timeout > idle)
+                .map(|timeout| timeout - idle)
+                .chain(flash)
+                .min();
+
+            match reader.read_event(timeout)? {
+                Some(event @ (Event::Key(_) | Event::Paste(_) | Event::Mouse(_))) => {
+                    return Ok(event)
+                }
+                Some(Event::Resize(columns, rows)) => {
+                    self.resize(columns, rows);
+                    self.refresh_screen()?;
+                }
+                Some(_) => {}
+                None => {
+                    self.idle(idle_since.elapsed());
+                    self.refresh_screen()?;
+                }
+            }
+        }
+    }
+
+    // Like read_input for when only a key will do, anything
+    // pasted is dropped
+    fn read_key(&mut self, reader: &mut Reader) -> io::Result<KeyEvent> {
+        loop {
+            if let Event::Key(key_event) = self.read_input(reader)? {
+                return Ok(key_event);
+            }
+        }
+    }
+
+    pub fn clear_screen() -> io::Result<()> {
+        // Associate function that will be called whenever
+        // there is a need to clear screen and relocate the cursor
+        // to the top left
+        SCREEN.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        execute!(stdout(), terminal::Clear(ClearType::All))?;
+        execute!(stdout(), cursor::MoveTo(0, 0))
+    }
+
+    // Edits and saves check this first, a read-only buffer
+    // says so instead of changing
+    fn is_read_only(&mut self) -> bool {
+        if self.read_only {
+            self.status_message
+                .set_message("Buffer is read-only".into());
+        }
+        self.read_only
+    }
+
+    pub fn insert_char(&mut self, ch: char) {
+        if self.is_read_only() {
+            return;
+        }
+        self.auto_closed.clear();
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let mut operations = Vec::new();
+
+        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            self.editor_rows
+                .insert_row(self.editor_rows.number_of_rows(), String::new());
+            operations.push(EditOperation::InsertNewline {
+                x: 0,
+                y: self.cursor_controller.cursor_y,
+            });
+
+            self.dirty += 1;
+        }
+
+        let tab_stop = self.editor_rows.tab_stop;
+        self.editor_rows
+            .get_editor_row_mut(self.cursor_controller.cursor_y)
+            .insert_char(self.cursor_controller.cursor_x, ch, tab_stop);
+        operations.push(EditOperation::InsertText {
+            x: self.cursor_controller.cursor_x,
+            y: self.cursor_controller.cursor_y,
+            text: ch.to_string(),
+        });
+
+        self.cursor_controller.cursor_x += 1;
+
+        // tracks that file has been modified
+        // counts the amount of changes
+        self.dirty += 1;
+        self.edit_history.push_insert_char(UndoEntry {
+            operations,
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    pub fn insert_newline(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        self.auto_closed.clear();
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+
+        self.editor_rows.split_row(
+            self.cursor_controller.cursor_y,
+            self.cursor_controller.cursor_x,
+        );
+
+        self.cursor_controller.cursor_x = 0;
+        self.cursor_controller.cursor_y += 1;
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![EditOperation::InsertNewline {
+                x: cursor_before.0,
+                y: cursor_before.1,
+            }],
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    // Used for characters typed by the user, which may auto
+    // close brackets and quotes when that is turned on
+    pub fn type_char(&mut self, ch: char) {
+        if self.is_read_only() {
+            return;
+        }
+        if !self.config.auto_close {
+            self.insert_char(ch);
+            return;
+        }
+
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let (previous_char, next_char) = if cursor_y < self.editor_rows.number_of_rows() {
+            let row = &self.editor_rows.get_editor_row(cursor_y).row_content;
+            (
+                cursor_x.checked_sub(1).and_then(|x| row.chars().nth(x)),
+                row.chars().nth(cursor_x),
+            )
+        } else {
+            (None, None)
+        };
+
+        if next_char == Some(ch) {
+            if let Some(index) = self
+                .auto_closed
+                .iter()
+                .position(|&position| position == (cursor_x, cursor_y))
+            {
+                self.auto_closed.remove(index);
+                self.cursor_controller.cursor_x += 1;
+                return;
+            }
+        }
+
+        let closing = match ch {
+            '(' => Some(')'),
+            '[' => Some(']'),
+            '{' => Some('}'),
+            // Quotes right after a word are most likely an
+            // apostrophe or a closing quote
+            '"' | '\'' if !previous_char.is_some_and(char::is_alphanumeric) => Some(ch),
+            _ => None,
+        };
+
+        // insert_char forgets auto closed characters like every
+        // other edit, typing keeps them and moves them along
+        let auto_closed = mem::take(&mut self.auto_closed);
+        self.insert_char(ch);
+        self.auto_closed = auto_closed;
+        self.shift_auto_closed(cursor_x, cursor_y);
+
+        if let Some(closing) = closing {
+            let (cursor_x, cursor_y) = (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            );
+            self.editor_rows
+                .insert_str(cursor_y, cursor_x, &closing.to_string());
+            self.shift_auto_closed(cursor_x, cursor_y);
+            self.auto_closed.push((cursor_x, cursor_y));
+
+            self.edit_history.extend_last(
+                EditOperation::InsertText {
+                    x: cursor_x,
+                    y: cursor_y,
+                    text: closing.to_string(),
+                },
+                (cursor_x, cursor_y),
+            );
+        }
+    }
+
+    // Moves auto closed characters along after a character
+    // is inserted in front of them
+    fn shift_auto_closed(&mut self, x: usize, y: usize) {
+        self.auto_closed
+            .iter_mut()
+            .filter(|position| position.1 == y && position.0 >= x)
+            .for_each(|position| position.0 += 1);
+    }
+
+    // Used for Enter, the new line starts with the same
+    // indentation as the line the cursor was on
+    fn insert_indented_newline(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let indentation = self.indentation_before_cursor();
+        self.insert_newline();
+
+        if indentation.is_empty() {
+            return;
+        }
+
+        let cursor_y = self.cursor_controller.cursor_y;
+        self.editor_rows.insert_str(cursor_y, 0, &indentation);
+        self.cursor_controller.cursor_x = indentation.chars().count();
+
+        self.edit_history.extend_last(
+            EditOperation::InsertText {
+                x: 0,
+                y: cursor_y,
+                text: indentation,
+            },
+            (self.cursor_controller.cursor_x, cursor_y),
+        );
+    }
+
+    // The leading whitespace of the current row (up to the
+    // cursor), rewritten as spaces or tabs depending on
+    // whether soft tabs are on
+    fn indentation_before_cursor(&self) -> String {
+        if self.cursor_controller.cursor_y >= self.editor_rows.number_of_rows() {
+            return String::new();
+        }
+
+        let row = self
+            .editor_rows
+            .get_editor_row(self.cursor_controller.cursor_y);
+        let indent_len = row
+            .row_content
+            .chars()
+            .take(self.cursor_controller.cursor_x)
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+        let width = row.get_render_x(indent_len, self.config.tab_stop);
+
+        if self.config.soft_tabs {
+            " ".repeat(width)
+        } else {
+            "\t".repeat(width / self.config.tab_stop) + &" ".repeat(width % self.config.tab_stop)
+        }
+    }
+
+    pub fn delete_char(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            return;
+        }
+        self.auto_closed.clear();
+
+        if self.cursor_controller.cursor_x == 0 && self.cursor_controller.cursor_y == 0 {
+            return;
+        }
+
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+
+        let tab_stop = self.editor_rows.tab_stop;
+        let row = self
+            .editor_rows
+            .get_editor_row_mut(self.cursor_controller.cursor_y);
+
+        let operation = if self.cursor_controller.cursor_x > 0 {
+            let start = row.previous_boundary(self.cursor_controller.cursor_x);
+            let deleted = row.delete_char(start, tab_stop);
+            self.cursor_controller.cursor_x = start;
+
+            EditOperation::DeleteText {
+                x: self.cursor_controller.cursor_x,
+                y: self.cursor_controller.cursor_y,
+                text: deleted,
+            }
+        } else {
+            let previous_row = self
+                .editor_rows
+                .get_editor_row(self.cursor_controller.cursor_y - 1);
+
+            self.cursor_controller.cursor_x = previous_row.len();
+
+            self.editor_rows
+                .join_adjacent_rows(self.cursor_controller.cursor_y);
+
+            self.cursor_controller.cursor_y -= 1;
+
+            EditOperation::JoinRows {
+                x: self.cursor_controller.cursor_x,
+                y: self.cursor_controller.cursor_y,
+            }
+        };
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![operation],
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    fn delete_key(&mut self, key: KeyCode) {
+        // Delete means delete char to the right
+        // this is done by moving the cursor to the right
+        // one step.
+
+        // Then regardless of whether Backspace or Delete
+        // is pressed, the appropriate function occurs
+        if matches!(key, KeyCode::Delete) {
+            self.move_cursor(KeyCode::Right)
+        }
+
+        let soft_tab_width = self.soft_tab_width_before_cursor();
+        if matches!(key, KeyCode::Backspace) && soft_tab_width > 1 {
+            self.delete_backward(soft_tab_width);
+        } else {
+            self.delete_char();
+        }
+    }
+
+    // Remembers an edit made from the keyboard for Alt+. to
+    // repeat. Characters typed where the last ones ended are
+    // added to the same insert
+    fn record_edit(&mut self, edit: RepeatableEdit, cursor_before: (usize, usize)) {
+        let cursor_after = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if let (RepeatableEdit::Insert(text), Some((RepeatableEdit::Insert(last), end))) =
+            (&edit, &mut self.last_edit)
+        {
+            if *end == cursor_before {
+                last.push_str(text);
+                *end = cursor_after;
+                return;
+            }
+        }
+        self.last_edit = Some((edit, cursor_after));
+    }
+
+    // Does an edit count times, undone all at once
+    fn edit_times(&mut self, count: usize, edit: fn(&mut Self)) {
+        let len = self.edit_history.undo_stack.len();
+        (0..count).for_each(|_| edit(self));
+        self.edit_history.merge_since(len);
+    }
+
+    // Does the last edit again at the cursor
+    fn repeat_edit(&mut self) {
+        let edit = match &self.last_edit {
+            None => return,
+            Some((edit, _)) => edit.clone(),
+        };
+
+        match edit {
+            RepeatableEdit::Insert(text) => text.chars().for_each(|ch| {
+                if ch == '\t' {
+                    self.insert_tab()
+                } else {
+                    self.type_char(ch)
+                }
+            }),
+            RepeatableEdit::Delete(key) => self.delete_key(key),
+            RepeatableEdit::Newline => self.insert_indented_newline(),
+            RepeatableEdit::DuplicateLine => self.duplicate_line(),
+            RepeatableEdit::DeleteLine => self.delete_line(),
+            RepeatableEdit::JoinLines => self.join_lines(),
+        }
+    }
+
+    // Pasted text is inserted as it is, without auto indent or
+    // auto close, and undone as a single edit
+    pub fn paste_text(&mut self, text: &str) {
+        let len = self.edit_history.undo_stack.len();
+        self.edit_history.coalesce = false;
+        self.insert_text(text);
+        self.edit_history.merge_since(len);
+        self.edit_history.coalesce = false;
+    }
+
+    // Types out text at the cursor, starting a new row for
+    // every line in it. Lines can end in \n, \r\n or just \r
+    fn insert_text(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.insert_newline();
+            }
+
+            line.chars().for_each(|ch| self.insert_char(ch));
+        }
+    }
+
+    fn insert_tab(&mut self) {
+        if !self.config.soft_tabs {
+            self.insert_char('\t');
+            return;
+        }
+
+        // Pad with spaces up to the next tab stop so soft tabs
+        // line up the same way a real tab would be rendered
+        let render_x = if self.cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+            self.cursor_controller.get_render_x(
+                self.editor_rows
+                    .get_editor_row(self.cursor_controller.cursor_y),
+                self.config.tab_stop,
+            )
+        } else {
+            0
+        };
+
+        (0..self.config.tab_stop - render_x % self.config.tab_stop)
+            .for_each(|_| self.insert_char(' '));
+    }
+
+    // With soft tabs, Backspace inside the leading
+    // indentation removes spaces back to the previous
+    // tab stop instead of a single space
+    fn soft_tab_width_before_cursor(&self) -> usize {
+        if !self.config.soft_tabs
+            || self.cursor_controller.cursor_y >= self.editor_rows.number_of_rows()
+        {
+            return 0;
+        }
+
+        let cursor_x = self.cursor_controller.cursor_x;
+        let row = self
+            .editor_rows
+            .get_editor_row(self.cursor_controller.cursor_y);
+
+        if cursor_x == 0 || !row.row_content.chars().take(cursor_x).all(|c| c == ' ') {
+            return 0;
+        }
+
+        (cursor_x - 1) % self.config.tab_stop + 1
+    }
+
+    // Deletes the count characters to the left of the cursor
+    // on the current row as a single edit
+    fn delete_backward(&mut self, count: usize) {
+        if self.is_read_only() {
+            return;
+        }
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let start = cursor_x - count;
+        let text: String = self
+            .editor_rows
+            .get_editor_row(cursor_y)
+            .row_content
+            .chars()
+            .skip(start)
+            .take(count)
+            .collect();
+
+        self.editor_rows.delete_str(cursor_y, start, count);
+        self.cursor_controller.cursor_x = start;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![EditOperation::DeleteText {
+                x: start,
+                y: cursor_y,
+                text,
+            }],
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (start, cursor_y),
+        });
+    }
+
+    // Strips the trailing whitespace of every row in the buffer
+    // itself (not just the written bytes) so the display matches
+    // the file, recorded as one edit so it can be undone
+    fn trim_trailing_whitespace(&mut self) {
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let mut operations = Vec::new();
+        for y in 0..self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_editor_row(y);
+            let len = row.len();
+            let trimmed_len = row.row_content.trim_end().chars().count();
+            if trimmed_len == len {
+                continue;
+            }
+            let text = row.row_content.chars().skip(trimmed_len).collect();
+            self.editor_rows.delete_str(y, trimmed_len, len - trimmed_len);
+            operations.push(EditOperation::DeleteText {
+                x: trimmed_len,
+                y,
+                text,
+            });
+        }
+        if operations.is_empty() {
+            return;
+        }
+
+        self.cursor_controller.clamp_cursor_x(&self.editor_rows);
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations,
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (self.cursor_controller.cursor_x, cursor_y),
+        });
+    }
+
+    // The rows Tab and the comment toggle work on, from the
+    // first to just past the last: every row the selection
+    // touches, or the current row without one. A selection
+    // ending at the start of a row leaves that row alone
+    fn selected_rows(&self) -> (usize, usize) {
+        let cursor_y = self.cursor_controller.cursor_y;
+        let (first, last) = match self.selection {
+            Some(selection) => {
+                let (start, end) = selection.bounds();
+                if end.1 > start.1 && end.0 == 0 {
+                    (start.1, end.1 - 1)
+                } else {
+                    (start.1, end.1)
+                }
+            }
+            None => (cursor_y, cursor_y),
+        };
+        (first, cmp::min(last + 1, self.editor_rows.number_of_rows()))
+    }
+
+    // Moves the cursor and the selection along with the text
+    // after an edit to each row from first on. An edit is the
+    // column it was made at and the number of characters added
+    // there, or removed if negative. Positions right at the
+    // column don't move, so a selection starting there takes in
+    // what was added
+    fn follow_row_edits(&mut self, first: usize, edits: &[(usize, isize)]) {
+        let follow = |(x, y): (usize, usize)| {
+            let edit = y.checked_sub(first).and_then(|i| edits.get(i));
+            match edit {
+                Some(&(at, by)) if x > at && by < 0 => {
+                    (cmp::max(at, x.saturating_sub(by.unsigned_abs())), y)
+                }
+                Some(&(at, by)) if x > at => (x + by as usize, y),
+                _ => (x, y),
+            }
+        };
+        if let Some(selection) = &mut self.selection {
+            selection.anchor = follow(selection.anchor);
+            selection.active = follow(selection.active);
+        }
+        let cursor = &mut self.cursor_controller;
+        (cursor.cursor_x, cursor.cursor_y) = follow((cursor.cursor_x, cursor.cursor_y));
+        self.auto_closed.clear();
+    }
+
+    // Indents (or with dedent, unindents) the selected rows by
+    // one level. The selection is kept, moved along with the
+    // text, so the rows can be shifted again
+    fn shift_lines(&mut self, dedent: bool) {
+        if self.is_read_only() {
+            return;
+        }
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let (first, last) = self.selected_rows();
+
+        let tab_stop = self.config.tab_stop;
+        let mut edits = vec![(0, 0); last.saturating_sub(first)];
+        let mut operations = Vec::new();
+        for y in first..last {
+            let row = &self.editor_rows.get_editor_row(y).row_content;
+            if dedent {
+                let width = if row.starts_with('\t') {
+                    1
+                } else {
+                    row.chars().take(tab_stop).take_while(|c| *c == ' ').count()
+                };
+                if width == 0 {
+                    continue;
+                }
+                let text = row.chars().take(width).collect();
+                self.editor_rows.delete_str(y, 0, width);
+                operations.push(EditOperation::DeleteText { x: 0, y, text });
+                edits[y - first] = (0, -(width as isize));
+            } else {
+                // Empty rows are skipped so no trailing
+                // whitespace is left behind
+                if row.is_empty() {
+                    continue;
+                }
+                let text = if self.config.soft_tabs {
+                    " ".repeat(tab_stop)
+                } else {
+                    "\t".into()
+                };
+                self.editor_rows.insert_str(y, 0, &text);
+                edits[y - first] = (0, text.len() as isize);
+                operations.push(EditOperation::InsertText { x: 0, y, text });
+            }
+        }
+        self.push_row_edits(operations, first, &edits, cursor_before);
+    }
+
+    // Comments out the selected rows with the line comment of
+    // the file's language, put after each row's indentation. If
+    // they're all comments already they're uncommented instead.
+    // Blank rows are left as they are
+    fn toggle_comment(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let (first, last) = self.selected_rows();
+        let prefix = self.editor_rows.syntax.line_comment();
+
+        // Where the text of each row starts, None for blank rows
+        let starts: Vec<Option<usize>> = (first..last)
+            .map(|y| {
+                let row = &self.editor_rows.get_editor_row(y).row_content;
+                row.chars().position(|c| !c.is_whitespace())
+            })
+            .collect();
+        let commented = starts.iter().enumerate().all(|(i, start)| {
+            start.is_none_or(|start| {
+                let row = &self.editor_rows.get_editor_row(first + i).row_content;
+                row.chars().skip(start).collect::<String>().starts_with(prefix)
+            })
+        });
+
+        let mut edits = vec![(0, 0); starts.len()];
+        let mut operations = Vec::new();
+        for (i, start) in starts.into_iter().enumerate() {
+            let (y, start) = match start {
+                Some(start) => (first + i, start),
+                None => continue,
+            };
+            if commented {
+                // The space after the comment marker goes too
+                let row = &self.editor_rows.get_editor_row(y).row_content;
+                let rest: String = row.chars().skip(start).collect();
+                let len = prefix.chars().count()
+                    + usize::from(rest[prefix.len()..].starts_with(' '));
+                let text = rest.chars().take(len).collect();
+                self.editor_rows.delete_str(y, start, len);
+                edits[i] = (start, -(len as isize));
+                operations.push(EditOperation::DeleteText { x: start, y, text });
+            } else {
+                let text = format!("{} ", prefix);
+                self.editor_rows.insert_str(y, start, &text);
+                edits[i] = (start, text.chars().count() as isize);
+                operations.push(EditOperation::InsertText { x: start, y, text });
+            }
+        }
+        self.push_row_edits(operations, first, &edits, cursor_before);
+    }
+
+    // Records the edits shift_lines and toggle_comment made as
+    // one undo entry
+    fn push_row_edits(
+        &mut self,
+        operations: Vec<EditOperation>,
+        first: usize,
+        edits: &[(usize, isize)],
+        cursor_before: (usize, usize),
+    ) {
+        if operations.is_empty() {
+            return;
+        }
+        self.follow_row_edits(first, edits);
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations,
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    // Deletes the whitespace before the cursor and the word
+    // before that. At the start of a row it joins the row
+    // with the previous one like Backspace
+    fn delete_word(&mut self) {
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if cursor_y >= self.editor_rows.number_of_rows() || cursor_x == 0 {
+            self.delete_char();
+            return;
+        }
+
+        let word_start = self
+            .editor_rows
+            .get_editor_row(cursor_y)
+            .previous_word_start(cursor_x);
+        self.delete_backward(cursor_x - word_start);
+    }
+
+    // Inserts a copy of the current line below it and moves
+    // the cursor onto the copy
+    fn duplicate_line(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let text = if cursor_y < self.editor_rows.number_of_rows() {
+            self.editor_rows
+                .get_editor_row(cursor_y)
+                .row_content
+                .clone()
+        } else {
+            String::new()
+        };
+        let y = cmp::min(cursor_y + 1, self.editor_rows.number_of_rows());
+
+        self.editor_rows.insert_row(y, text.clone());
+        self.cursor_controller.cursor_y = cursor_y + 1;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![EditOperation::InsertRow { y, text }],
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (cursor_x, self.cursor_controller.cursor_y),
+        });
+    }
+
+    // Removes the whole current line, the cursor stays on the
+    // same y so repeated presses clear the lines below it
+    fn delete_line(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if cursor_y >= self.editor_rows.number_of_rows() {
+            return;
+        }
+        let text = self
+            .editor_rows
+            .get_editor_row(cursor_y)
+            .row_content
+            .clone();
+
+        self.editor_rows.remove_row(cursor_y);
+        self.cursor_controller.cursor_y =
+            cmp::min(cursor_y, self.editor_rows.number_of_rows());
+        self.cursor_controller.clamp_cursor_x(&self.editor_rows);
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![EditOperation::RemoveRow { y: cursor_y, text }],
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    // Appends the next row to the current one like vim's J,
+    // without its indentation and after a single space. The
+    // cursor goes to where they were joined
+    fn join_lines(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        if cursor_y + 1 >= self.editor_rows.number_of_rows() {
+            self.ring_bell();
+            return;
+        }
+
+        let row = &self.editor_rows.get_editor_row(cursor_y).row_content;
+        let next_row = &self.editor_rows.get_editor_row(cursor_y + 1).row_content;
+        let indentation = &next_row[..next_row.len() - next_row.trim_start().len()];
+        let x = row.chars().count();
+        // No space is added next to one that's already there
+        // or when either row has nothing to be separated from
+        let separated = row.ends_with(char::is_whitespace)
+            || row.is_empty()
+            || next_row.trim_start().is_empty();
+
+        let mut operations = Vec::new();
+        if !indentation.is_empty() {
+            operations.push(EditOperation::DeleteText {
+                x: 0,
+                y: cursor_y + 1,
+                text: indentation.to_string(),
+            });
+        }
+        operations.push(EditOperation::JoinRows { x, y: cursor_y });
+        if !separated {
+            operations.push(EditOperation::InsertText {
+                x,
+                y: cursor_y,
+                text: " ".into(),
+            });
+        }
+        operations
+            .iter()
+            .for_each(|operation| self.apply_operation(operation));
+        self.cursor_controller.cursor_x = x;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations,
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (x, cursor_y),
+        });
+    }
+
+    // Swaps the current line with the one above (Up) or
+    // below (Down), the cursor moves along with the line
+    fn move_line(&mut self, direction: KeyCode) {
+        if self.is_read_only() {
+            return;
+        }
+        let (cursor_x, cursor_y) = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let (y, new_cursor_y) = match direction {
+            KeyCode::Up if cursor_y > 0 && cursor_y < self.editor_rows.number_of_rows() => {
+                (cursor_y - 1, cursor_y - 1)
+            }
+            KeyCode::Down if cursor_y + 1 < self.editor_rows.number_of_rows() => {
+                (cursor_y, cursor_y + 1)
+            }
+            _ => return,
+        };
+
+        self.editor_rows.swap_rows(y, y + 1);
+        self.cursor_controller.cursor_y = new_cursor_y;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![EditOperation::SwapRows { y }],
+            cursor_before: (cursor_x, cursor_y),
+            cursor_after: (cursor_x, new_cursor_y),
+        });
+    }
+
+    fn apply_operation(&mut self, operation: &EditOperation) {
+        match operation {
+            EditOperation::InsertText { x, y, text } => self.editor_rows.insert_str(*y, *x, text),
+            EditOperation::DeleteText { x, y, text } => {
+                self.editor_rows.delete_str(*y, *x, text.chars().count())
+            }
+            EditOperation::InsertNewline { x, y } => self.editor_rows.split_row(*y, *x),
+            EditOperation::JoinRows { y, .. } => self.editor_rows.join_adjacent_rows(*y + 1),
+            EditOperation::InsertRow { y, text } => self.editor_rows.insert_row(*y, text.clone()),
+            EditOperation::SwapRows { y } => self.editor_rows.swap_rows(*y, *y + 1),
+            EditOperation::RemoveRow { y, .. } => self.editor_rows.remove_row(*y),
+        }
+    }
+
+    fn reverse_operation(&mut self, operation: &EditOperation) {
+        match operation {
+            EditOperation::InsertText { x, y, text } => {
+                self.editor_rows.delete_str(*y, *x, text.chars().count())
+            }
+            EditOperation::DeleteText { x, y, text } => self.editor_rows.insert_str(*y, *x, text),
+            EditOperation::InsertNewline { y, .. } => {
+                if *y + 1 < self.editor_rows.number_of_rows() {
+                    self.editor_rows.join_adjacent_rows(*y + 1)
+                } else {
+                    self.editor_rows.remove_row(*y)
+                }
+            }
+            EditOperation::JoinRows { x, y } => self.editor_rows.split_row(*y, *x),
+            EditOperation::InsertRow { y, .. } => self.editor_rows.remove_row(*y),
+            EditOperation::SwapRows { y } => self.editor_rows.swap_rows(*y, *y + 1),
+            EditOperation::RemoveRow { y, text } => self.editor_rows.insert_row(*y, text.clone()),
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let entry = match self.edit_history.undo_stack.pop() {
+            None => {
+                self.status_message.set_message("Nothing to undo".into());
+                self.ring_bell();
+                return;
+            }
+            Some(entry) => entry,
+        };
+
+        entry
+            .operations
+            .iter()
+            .rev()
+            .for_each(|operation| self.reverse_operation(operation));
+
+        self.cursor_controller.cursor_x = entry.cursor_before.0;
+        self.cursor_controller.cursor_y = entry.cursor_before.1;
+        self.edit_history.redo_stack.push(entry);
+        self.edit_history.coalesce = false;
+        self.auto_closed.clear();
+        self.update_dirty_from_history();
+    }
+
+    pub fn redo(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        let entry = match self.edit_history.redo_stack.pop() {
+            None => {
+                self.status_message.set_message("Nothing to redo".into());
+                self.ring_bell();
+                return;
+            }
+            Some(entry) => entry,
+        };
+
+        entry
+            .operations
+            .iter()
+            .for_each(|operation| self.apply_operation(operation));
+
+        self.cursor_controller.cursor_x = entry.cursor_after.0;
+        self.cursor_controller.cursor_y = entry.cursor_after.1;
+        self.edit_history.undo_stack.push(entry);
+        self.edit_history.coalesce = false;
+        self.auto_closed.clear();
+        self.update_dirty_from_history();
+    }
+
+    fn update_dirty_from_history(&mut self) {
+        // Undoing/redoing back to the saved state means
+        // there is nothing left to save
+        if self.edit_history.is_saved() {
+            self.dirty = 0;
+        } else {
+            self.dirty += 1;
+        }
+    }
+
+    fn find_callback(output: &mut Output, keyword: &str, key_code: KeyCode) {
+        match key_code {
+            // Only a confirmed search rings the bell when nothing
+            // matched, not every key typed on the way to it
+            KeyCode::Enter
+                if output.search_index.current_match.is_none() && !keyword.is_empty() =>
+            {
+                output.ring_bell();
+                output.search_index.reset();
+            }
+            KeyCode::Esc | KeyCode::Enter => {
+                output.search_index.reset();
+            }
+            _ => {
+                output.search_index.y_direction = None;
+                output.search_index.x_direction = None;
+
+                match key_code {
+                    KeyCode::Down => {
+                        output.search_index.y_direction = SearchDirection::Forward.into()
+                    }
+                    KeyCode::Up => {
+                        output.search_index.y_direction = SearchDirection::Backward.into()
+                    }
+                    KeyCode::Left => {
+                        output.search_index.x_direction = SearchDirection::Backward.into()
+                    }
+                    KeyCode::Right => {
+                        output.search_index.x_direction = SearchDirection::Forward.into()
+                    }
+                    // Ctrl+I, which terminals send as Tab
+                    KeyCode::Tab => {
+                        output.search_index.case_insensitive =
+                            !output.search_index.case_insensitive
+                    }
+                    _ => {}
+                }
+
+                output.search_index.keyword = keyword.to_string();
+                if output.search_index.x_direction.is_none()
+                    && output.search_index.y_direction.is_none()
+                {
+                    output.search_index.current_match = None;
+                }
+
+                let case_insensitive = output.search_index.case_insensitive;
+                let counted = Some((keyword.to_string(), case_insensitive));
+                let keyword = if case_insensitive {
+                    keyword.to_lowercase()
+                } else {
+                    keyword.to_string()
+                };
+
+                if output.search_index.counted != counted {
+                    let editor_rows = &output.editor_rows;
+                    output.search_index.matches = (0..editor_rows.number_of_rows())
+                        .flat_map(|y| {
+                            editor_rows
+                                .get_editor_row(y)
+                                .find_all(&keyword, case_insensitive)
+                                .into_iter()
+                                .map(move |(start, _)| (y, start))
+                        })
+                        .collect();
+                    output.search_index.counted = counted;
+                }
+
+                for i in 0..output.editor_rows.number_of_rows() {
+                    let row_index = match output.search_index.y_direction.as_ref() {
+                        None => {
+                            if output.search_index.x_direction.is_none() {
+                                output.search_index.y_index = i;
+                            }
+
+                            output.search_index.y_index
+                        }
+                        Some(dir) => {
+                            if matches!(dir, SearchDirection::Forward) {
+                                output.search_index.y_index + i + 1
+                            } else {
+                                let res = output.search_index.y_index.saturating_sub(i);
+                                if res == 0 {
+                                    break;
+                                }
+                                res - 1
+                            }
+                        }
+                    };
+
+                    if row_index > output.editor_rows.number_of_rows() - 1 {
+                        break;
+                    }
+
+                    let row = output.editor_rows.get_editor_row(row_index);
+                    let index = match output.search_index.x_direction.as_ref() {
+                        None => row.find(&keyword, 0, case_insensitive),
+                        Some(dir) => {
+                            let index = if matches!(dir, SearchDirection::Forward) {
+                                row.find(&keyword, output.search_index.x_index + 1, case_insensitive)
+                            } else {
+                                row.rfind(&keyword, output.search_index.x_index, case_insensitive)
+                            };
+
+                            if index.is_none() {
+                                break;
+                            }
+
+                            index
+                        }
+                    };
+
+                    if let Some(index) = index {
+                        output.cursor_controller.cursor_y = row_index;
+                        output.search_index.y_index = row_index;
+                        output.search_index.x_index = index;
+                        output.search_index.current_match = Some((row_index, index));
+
+                        output.cursor_controller.cursor_x =
+                            row.get_row_content_x(index, output.editor_rows.tab_stop);
+                        output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn find(&mut self, reader: &mut Reader, history: &mut Vec<String>) -> io::Result<()> {
+        let cursor_controller = self.cursor_controller;
+        self.search_index.active = true;
+
+        let keyword = prompt!(
+            self,
+            reader,
+            "Search: {} (ESC to cancel, Arrows to find next matches, Ctrl+I to toggle case, Enter to find)",
+            callback = Output::find_callback,
+            history = history
+        );
+        self.search_index.active = false;
+
+        match keyword {
+            Some(keyword) => self.search_index.confirmed = keyword,
+            None => self.cursor_controller = cursor_controller,
+        }
+        Ok(())
+    }
+
+    // Moves to the next (or previous) match of the last
+    // confirmed search without opening the prompt. The rest
+    // of the current row is looked at first, then the rows
+    // after (or before) it
+    fn find_again(&mut self, forward: bool) {
+        let keyword = self.search_index.confirmed.clone();
+        if keyword.is_empty() {
+            self.status_message
+                .set_message("Nothing searched for yet, Ctrl+F starts a search".into());
+            return;
+        }
+        if self.editor_rows.number_of_rows() == 0 {
+            return;
+        }
+        // From the empty row after the end, the search goes
+        // from the end of the last one
+        let rows = self.editor_rows.number_of_rows();
+        if self.cursor_controller.cursor_y >= rows {
+            self.cursor_controller.cursor_y = rows - 1;
+            self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(rows - 1).len();
+        }
+
+        let cursor = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let row = self.editor_rows.get_editor_row(cursor.1);
+        self.search_index.y_index = cursor.1;
+        self.search_index.x_index = row.get_render_x(cursor.0, self.editor_rows.tab_stop);
+
+        let (in_row, other_rows) = if forward {
+            (KeyCode::Right, KeyCode::Down)
+        } else {
+            (KeyCode::Left, KeyCode::Up)
+        };
+        let mut found = false;
+        for key_code in [in_row, other_rows] {
+            Self::find_callback(self, &keyword, key_code);
+            found = (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ) != cursor;
+            if found {
+                break;
+            }
+        }
+        if !found {
+            self.status_message
+                .set_message(format!("No more matches of {}", keyword));
+            self.ring_bell();
+        }
+        self.search_index.reset();
+    }
+
+    // Replaces the characters from start_x to end_x
+    // on row y as a single edit
+    fn replace_text(&mut self, y: usize, start_x: usize, end_x: usize, replacement: &str) {
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let text: String = self
+            .editor_rows
+            .get_editor_row(y)
+            .row_content
+            .chars()
+            .skip(start_x)
+            .take(end_x - start_x)
+            .collect();
+
+        self.editor_rows.delete_str(y, start_x, end_x - start_x);
+        self.editor_rows.insert_str(y, start_x, replacement);
+
+        self.cursor_controller.cursor_x = start_x + replacement.chars().count();
+        self.cursor_controller.cursor_y = y;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations: vec![
+                EditOperation::DeleteText {
+                    x: start_x,
+                    y,
+                    text,
+                },
+                EditOperation::InsertText {
+                    x: start_x,
+                    y,
+                    text: replacement.to_string(),
+                },
+            ],
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    fn replace(&mut self, reader: &mut Reader, history: &mut Vec<String>) -> io::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
+        let keyword = match prompt!(self, reader, "Search: {} (ESC to cancel)", history = history) {
+            None => return Ok(()),
+            Some(keyword) => keyword,
+        };
+        let replacement = match prompt!(self, reader, "Replace with: {} (ESC to cancel)") {
+            None => return Ok(()),
+            Some(replacement) => replacement,
+        };
+
+        let case_insensitive = self.search_index.case_insensitive;
+        let search_keyword = if case_insensitive {
+            keyword.to_lowercase()
+        } else {
+            keyword.clone()
+        };
+
+        // Highlight the matches like the find prompt does
+        // while asking about each of them
+        self.search_index.active = true;
+        self.search_index.keyword = keyword;
+
+        let (mut x, mut y) = (0, 0);
+        let mut replace_all = false;
+        let mut replaced = 0;
+        // All the replacements are undone as one edit
+        let len = self.edit_history.undo_stack.len();
+
+        while y < self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_editor_row(y);
+            let (start_x, end_x) = match row.find_in_content(&search_keyword, x, case_insensitive)
+            {
+                None => {
+                    x = 0;
+                    y += 1;
+                    continue;
+                }
+                Some(found) => found,
+            };
+
+            if !replace_all {
+                self.search_index.current_match =
+                    Some((y, row.get_render_x(start_x, self.editor_rows.tab_stop)));
+                self.cursor_controller.cursor_x = start_x;
+                self.cursor_controller.cursor_y = y;
+                self.status_message.set_message_for(
+                    "Replace this match? (y)es (n)o (a)ll (ESC to stop)".into(),
+                    None,
+                );
+                self.refresh_screen()?;
+
+                let answer = loop {
+                    if let code @ (KeyCode::Char('y' | 'n' | 'a' | 'q') | KeyCode::Esc) =
+                        self.read_key(reader)?.code
+                    {
+                        break code;
+                    }
+                };
+
+                match answer {
+                    KeyCode::Char('y') => {}
+                    KeyCode::Char('a') => replace_all = true,
+                    KeyCode::Char('n') => {
+                        x = end_x;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+
+            self.replace_text(y, start_x, end_x, &replacement);
+            replaced += 1;
+            x = start_x + replacement.chars().count();
+        }
+        self.edit_history.merge_since(len);
+
+        self.search_index.reset();
+        self.search_index.active = false;
+        self.status_message
+            .set_message(format!("Replaced {} occurrence(s)", replaced));
+        Ok(())
+    }
+
+    fn goto_line(&mut self, reader: &mut Reader) -> io::Result<()> {
+        if let Some(input) = prompt!(self, reader, "Go to line: {} (ESC to cancel)") {
+            self.jump_to_line(&input);
+        }
+        Ok(())
+    }
+
+    // Moves the cursor to the line numbered in input,
+    // counting from 1
+    fn jump_to_line(&mut self, input: &str) {
+        match input.trim().parse::<usize>() {
+            Ok(line) if line > 0 => {
+                self.cursor_controller.cursor_y = cmp::min(
+                    line - 1,
+                    self.editor_rows.number_of_rows().saturating_sub(1),
+                );
+                self.cursor_controller.clamp_cursor_x(&self.editor_rows);
+            }
+            _ => self
+                .status_message
+                .set_message("Not a valid line number".into()),
+        }
+    }
+
+    // Changes an option of this buffer from the command line,
+    // written like in the config file. A value that isn't
+    // valid TOML is taken as a string, and an option given
+    // without one is turned on
+    fn set_option(&mut self, setting: &str) {
+        let (key, value) = match setting.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (setting.trim(), "true"),
+        };
+        let key = if key == "tabstop" { "tab_stop" } else { key };
+        if !Config::OPTIONS.contains(&key) {
+            self.status_message
+                .set_message(format!("Unknown option: {}", key));
+            return;
+        }
+
+        let table = format!("{} = {}", key, value)
+            .parse::<toml::Table>()
+            .or_else(|_| format!("{} = {:?}", key, value).parse::<toml::Table>());
+        let mut config = self.config.clone();
+        match table.map_err(|err| err.to_string()).and_then(|table| config.apply(&table)) {
+            Ok(()) => {
+                if config.tab_stop != self.config.tab_stop {
+                    self.editor_rows.set_tab_stop(config.tab_stop);
+                }
+                if config.theme != self.config.theme
+                    || config.color_depth != self.config.color_depth
+                    || config.spell_check != self.config.spell_check
+                {
+                    self.editor_rows.forget_drawn();
+                }
+                self.editor_rows.override_line_ending(&config);
+                self.status_message.default_timeout = config.status_timeout;
+                self.config = config;
+                self.status_message.set_message(format!("Set {}", setting.trim()));
+            }
+            Err(err) => self.status_message.set_message(err),
+        }
+    }
+
+    // Writes the buffer to its file, applying the save options
+    // from the config. The buffer must already have a name and
+    // not be read-only
+    pub fn save(&mut self) -> io::Result<usize> {
+        if self.read_only {
+            return Err(io::Error::other("the buffer is read-only"));
+        }
+        if self.config.trim_trailing_whitespace {
+            self.trim_trailing_whitespace();
+        }
+
+        let len = self.editor_rows.save(self.config.trailing_newline)?;
+        self.dirty = 0;
+        self.edit_history.mark_saved();
+        self.remove_swap();
+        self.remember_position();
+        Ok(len)
+    }
+
+    // Replaces the buffer with the output of the formatter for
+    // its file type, as one edit that can be undone. Only the
+    // rows between the unchanged start and end of the file are
+    // replaced, so the cursor stays on the same line unless the
+    // formatter changed the lines around it
+    fn format(&mut self) -> Result<(), String> {
+        let command = match self.config.formatters.get(self.editor_rows.syntax.file_type()) {
+            Some(command) if self.config.format_on_save => command,
+            _ => return Ok(()),
+        };
+
+        let contents: String = self
+            .editor_rows
+            .row_contents
+            .iter()
+            .flat_map(|row| [row.row_content.as_str(), "\n"])
+            .collect();
+        let formatted = run_formatter(command, contents)?;
+        let formatted: Vec<&str> = formatted.lines().collect();
+
+        let old_len = self.editor_rows.number_of_rows();
+        let rows = &self.editor_rows;
+        let prefix = (0..cmp::min(old_len, formatted.len()))
+            .take_while(|&y| rows.get_editor_row(y).row_content == formatted[y])
+            .count();
+        let suffix = (1..=cmp::min(old_len, formatted.len()) - prefix)
+            .take_while(|&i| {
+                rows.get_editor_row(old_len - i).row_content == formatted[formatted.len() - i]
+            })
+            .count();
+        if prefix == old_len && prefix == formatted.len() {
+            return Ok(());
+        }
+
+        let mut operations = Vec::new();
+        for _ in prefix..old_len - suffix {
+            let text = self.editor_rows.get_editor_row(prefix).row_content.clone();
+            self.editor_rows.remove_row(prefix);
+            operations.push(EditOperation::RemoveRow { y: prefix, text });
+        }
+        for (y, text) in formatted.iter().enumerate().take(formatted.len() - suffix).skip(prefix) {
+            self.editor_rows.insert_row(y, text.to_string());
+            operations.push(EditOperation::InsertRow {
+                y,
+                text: text.to_string(),
+            });
+        }
+
+        let cursor_before = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let cursor_controller = &mut self.cursor_controller;
+        if cursor_controller.cursor_y >= old_len - suffix {
+            cursor_controller.cursor_y = cursor_controller.cursor_y + formatted.len() - old_len;
+        } else {
+            cursor_controller.cursor_y = cmp::min(cursor_controller.cursor_y, formatted.len());
+        }
+        cursor_controller.clamp_cursor_x(&self.editor_rows);
+        self.selection = None;
+        self.auto_closed.clear();
+
+        self.dirty += 1;
+        self.edit_history.push(UndoEntry {
+            operations,
+            cursor_before,
+            cursor_after: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+        Ok(())
+    }
+
+    // Writes the unsaved changes to the buffer's recovery file.
+    // A failed write is remembered too, so it isn't retried
+    // until there are more changes
+    fn write_swap(&mut self) {
+        let path = self.editor_rows.swap_path();
+        if self.swap.as_ref().is_some_and(|(old_path, _)| *old_path != path) {
+            // The buffer has been named since
+            self.remove_swap();
+        }
+
+        if let Err(err) = self.editor_rows.write_to(&path, true) {
+            self.status_message
+                .set_message(format!("Could not write recovery file: {}", err));
+        }
+        self.swap = Some((path, self.dirty));
+    }
+
+    fn remove_swap(&mut self) {
+        if let Some((path, _)) = self.swap.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // How long after the last key press the recovery file
+    // is written and the buffer autosaved, while there are
+    // changes for them to pick up
+    fn idle_timeouts(&self) -> [Option<Duration>; 2] {
+        if self.dirty == 0 {
+            return [None, None];
+        }
+
+        let swap_is_stale = self.swap.as_ref().map(|(_, dirty)| *dirty) != Some(self.dirty);
+        [
+            Some(SWAP_INTERVAL).filter(|_| swap_is_stale),
+            self.config
+                .autosave
+                .filter(|_| self.editor_rows.filename.is_some() && !self.read_only),
+        ]
+    }
+
+    fn idle(&mut self, idle: Duration) {
+        let [swap, autosave] = self.idle_timeouts();
+        if swap.is_some_and(|timeout| idle >= timeout) {
+            self.write_swap();
+        }
+        if autosave.is_some_and(|timeout| idle >= timeout) {
+            self.autosave();
+        }
+    }
+
+    // Called once no key has been pressed for the autosave
+    // interval. Only the buffer being edited is saved, and
+    // never one without a file name or a read-only one. That's
+    // checked quietly, is_read_only would say so every time
+    fn autosave(&mut self) {
+        if self.read_only
+            || self.dirty == 0
+            || self.editor_rows.filename.is_none()
+            || self.editor_rows.changed_on_disk().is_some()
+        {
+            return;
+        }
+
+        let message = match self.save() {
+            Ok(_) => format!("Auto-saved {}", self.editor_rows.display_name()),
+            Err(err) => format!("Auto-save failed: {}", err),
+        };
+        self.status_message.set_message(message);
+    }
+
+    // Lets the user know, once for each change, that another
+    // program changed the file
+    fn check_disk_change(&mut self) {
+        let modified = self.editor_rows.changed_on_disk();
+        if modified.is_none() || modified == self.disk_change_shown {
+            return;
+        }
+
+        self.disk_change_shown = modified;
+        self.status_message.set_message_for(
+            format!(
+                "{} was changed on disk. Press Alt+R to reload it.",
+                self.editor_rows.display_name()
+            ),
+            Some(WARNING_TIMEOUT),
+        );
+    }
+
+    // Loads the file again, unsaved changes are only thrown
+    // away once the user confirms it
+    fn reload(&mut self, reader: &mut Reader) -> io::Result<()> {
+        let filename = match &self.editor_rows.filename {
+            None => {
+                self.status_message
+                    .set_message("The buffer has no file to reload".into());
+                return Ok(());
+            }
+            Some(filename) => filename.clone(),
+        };
+
+        if self.dirty > 0 {
+            let answer = prompt!(self, reader, "Discard unsaved changes and reload? (y/N) {}");
+            if !answer.is_some_and(|answer| answer.eq_ignore_ascii_case("y")) {
+                self.status_message.set_message("Reload aborted".into());
+                return Ok(());
+            }
+        }
+
+        match EditorRows::from_file(filename, self.config.tab_stop) {
+            Ok(mut editor_rows) => {
+                editor_rows.override_line_ending(&self.config);
+                self.editor_rows = editor_rows;
+                self.dirty = 0;
+                self.edit_history = EditHistory::new();
+                self.selection = None;
+                self.auto_closed.clear();
+                self.disk_change_shown = None;
+                self.remove_swap();
+
+                let number_of_rows = self.editor_rows.number_of_rows();
+                let cursor_controller = &mut self.cursor_controller;
+                cursor_controller.cursor_y = cmp::min(cursor_controller.cursor_y, number_of_rows);
+                cursor_controller.clamp_cursor_x(&self.editor_rows);
+                self.status_message.set_message(format!(
+                    "Reloaded {}",
+                    self.editor_rows.display_name()
+                ));
+            }
+            Err(err) => self
+                .status_message
+                .set_message(format!("Could not reload: {}", err)),
+        }
+        Ok(())
+    }
+
+    // Loads the file named in the prompt, the editor decides
+    // which buffer it goes into. Nothing is returned if the
+    // prompt is cancelled or the file can't be read. The prompt
+    // starts out in the directory of the current file
+    fn open_file(&mut self, reader: &mut Reader) -> io::Result<Option<EditorRows>> {
+        let directory = self
+            .editor_rows
+            .filename
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .map_or_else(String::new, |directory| {
+                format!("{}{}", directory.display(), std::path::MAIN_SEPARATOR)
+            });
+        match prompt!(
+            self,
+            reader,
+            "Open: {} (ESC to cancel)",
+            initial = directory,
+            paths = true
+        ) {
+            None => Ok(None),
+            Some(file) => Ok(self.load_file(&file)),
+        }
+    }
+
+    fn load_file(&mut self, file: &str) -> Option<EditorRows> {
+        match EditorRows::from_file(file.into(), self.config.tab_stop) {
+            Ok(mut editor_rows) => {
+                editor_rows.override_line_ending(&self.config);
+                Some(editor_rows)
+            }
+            Err(err) => {
+                self.status_message
+                    .set_message(format!("Could not open {}: {}", file, err));
+                None
+            }
+        }
+    }
+
+    // Writes the buffer out as highlighted HTML, next to the
+    // file as name.html unless another path is given
+    fn export_html(&mut self, path: &str) {
+        let path = match (path, &self.editor_rows.filename) {
+            ("", Some(filename)) => PathBuf::from(format!("{}.html", filename.display())),
+            ("", None) => {
+                self.status_message
+                    .set_message("The buffer has no name, give export a file to write".into());
+                return;
+            }
+            (path, _) => PathBuf::from(path),
+        };
+        let message = match fs::write(&path, self.editor_rows.to_html(&self.config.theme)) {
+            Ok(()) => format!("Exported to {}", path.display()),
+            Err(err) => format!("Could not export to {}: {}", path.display(), err),
+        };
+        self.status_message.set_message(message);
+    }
+
+    // An unnamed buffer that was never typed in, opening a
+    // file replaces it instead of adding another buffer.
+    // Unsaved changes are never replaced, even recovered ones
+    fn is_pristine(&self) -> bool {
+        self.dirty == 0
+            && self.editor_rows.filename.is_none()
+            && self.editor_rows.number_of_rows() == 0
+            && self.edit_history.undo_stack.is_empty()
+    }
+
+    // Moves the cursor while keeping the position it
+    // started from as the anchor of the selection
+    fn extend_selection(&mut self, direction: KeyCode) {
+        let cursor = (
+            self.cursor_controller.cursor_x,
+            self.cursor_controller.cursor_y,
+        );
+        let anchor = self.selection.map_or(cursor, |selection| selection.anchor);
+
+        self.move_cursor(direction);
+        self.selection = Some(Selection {
+            anchor,
+            active: (
+                self.cursor_controller.cursor_x,
+                self.cursor_controller.cursor_y,
+            ),
+        });
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        let selection = self.selection?;
+        let (start, end) = selection.bounds();
+
+        let text = (start.1..=end.1)
+            .map(|y| {
+                if y >= self.editor_rows.number_of_rows() {
+                    return String::new();
+                }
+
+                let row = self.editor_rows.get_editor_row(y);
+                let (start_x, end_x) = selection.row_range(y, row).unwrap();
+                row.row_content
+                    .chars()
+                    .skip(start_x)
+                    .take(end_x - start_x)
+                    .collect()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        Some(text)
+    }
+
+    // Shows the counts of the whole buffer, and of the
+    // selection first if there is one
+    fn show_stats(&mut self) {
+        let stats = self.editor_rows.stats().describe();
+        let message = match self.selected_text() {
+            Some(text) => format!(
+                "Selection: {} | File: {}",
+                TextStats::count(text.split('\n'), "\n").describe(),
+                stats
+            ),
+            None => stats,
+        };
+        self.status_message.set_message(message);
+    }
+
+    // Width of the line number column, including the space
+    // separating it from the text. Grows with the number of
+    // rows, and is 0 when line numbers are turned off
+    fn gutter_width(&self) -> usize {
+        if self.config.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+
+        cmp::max(self.editor_rows.number_of_rows().to_string().len(), 4) + 1
+    }
+
+    // Columns on the right taken up by the scrollbar
+    fn scrollbar_width(&self) -> usize {
+        usize::from(self.config.scrollbar)
+    }
+
+    // The screen rows the scrollbar's thumb covers, sized and
+    // placed like the rows on screen are within the file.
+    // Rows past the end of the file count when scrolled
+    // down to them
+    fn scrollbar_thumb(&self) -> Range<usize> {
+        let screen_rows = self.win_size.1;
+        let row_offset = self.cursor_controller.row_offset;
+        let number_of_rows = cmp::max(self.editor_rows.number_of_rows(), row_offset + screen_rows);
+        if number_of_rows == 0 {
+            return 0..0;
+        }
+
+        let length = cmp::max(screen_rows * screen_rows / number_of_rows, 1).min(screen_rows);
+        let start = cmp::min(row_offset * screen_rows / number_of_rows, screen_rows - length);
+        start..start + length
+    }
+
+    fn draw_rows(&mut self) {
+        // Draws each row in the terminal window based on the size
+        // saved when initialized. Includes drawing the ~ at the start
+        // of each row and also a welcome message at the horizontal center
+        // of the screen, a third of the way down vertically.
+        let screen_rows = self.win_size.1;
+        let gutter_width = self.gutter_width();
+        let screen_columns = self
+            .win_size
+            .0
+            .saturating_sub(gutter_width + self.scrollbar_width());
+        let x_offset = self.x_offset as u16;
+        let reaches_right_edge = self.reaches_right_edge();
+        let theme = self.config.theme.fit(self.config.color_depth);
+        let scrollbar_thumb = self.scrollbar_thumb();
+
+        // While the find prompt is open every match of the
+        // keyword on screen is highlighted
+        let search_keyword = Some(&self.search_index.keyword)
+            .filter(|keyword| self.search_index.active && !keyword.is_empty())
+            .map(|keyword| {
+                if self.search_index.case_insensitive {
+                    keyword.to_lowercase()
+                } else {
+                    keyword.clone()
+                }
+            });
+
+        // The file row and the range of its render columns
+        // drawn on each screen row
+        let row_offset = self.cursor_controller.row_offset;
+        let column_offset = self.cursor_controller.column_offset;
+        let lines: Vec<(usize, usize, usize)> = if self.config.soft_wrap {
+            (row_offset..)
+                .flat_map(|file_row| {
+                    let starts = self.cursor_controller.wrap_starts(&self.editor_rows, file_row);
+                    let ends: Vec<usize> = starts
+                        .iter()
+                        .skip(1)
+                        .copied()
+                        .chain([starts[starts.len() - 1] + screen_columns])
+                        .collect();
+                    starts
+                        .into_iter()
+                        .zip(ends)
+                        .map(move |(start, end)| (file_row, start, end))
+                })
+                .take(screen_rows)
+                .collect()
+        } else {
+            (0..screen_rows)
+                .map(|i| (row_offset + i, column_offset, column_offset + screen_columns))
+                .collect()
+        };
+
+        for (i, (file_row, start, end)) in lines.into_iter().enumerate() {
+
+            // A pane that doesn't reach the edge of the terminal
+            // is blanked first and followed by the divider
+            self.editor_contents.start_line(x_offset, i as u16);
+            if !reaches_right_edge {
+                (0..self.win_size.0).for_each(|_| self.editor_contents.push(' '));
+                self.editor_contents.push('\u{2502}');
+                let _ = queue!(self.editor_contents, cursor::MoveTo(x_offset, i as u16));
+            }
+
+            if file_row >= self.editor_rows.number_of_rows() {
+                // The welcome lines start a third of the way down,
+                // the ones that don't fit below that are left out
+                let welcome = i
+                    .checked_sub(screen_rows / 3)
+                    .and_then(|line| self.config.welcome.get(line))
+                    .filter(|_| self.editor_rows.number_of_rows() == 0)
+                    .map(|line| line.replace("{version}", &VERSION.to_string()));
+                if let Some(line) = welcome {
+                    let width = self.win_size.0.saturating_sub(self.scrollbar_width());
+                    let mut welcome = String::new();
+                    let mut welcome_width = 0;
+                    for grapheme in line.graphemes(true) {
+                        if welcome_width + grapheme_width(grapheme) > width {
+                            break;
+                        }
+                        welcome.push_str(grapheme);
+                        welcome_width += grapheme_width(grapheme);
+                    }
+
+                    let mut padding = (width - welcome_width) / 2;
+                    if padding != 0 {
+                        self.editor_contents.push('~');
+                        padding -= 1;
+                    }
+                    (0..padding).for_each(|_| self.editor_contents.push(' '));
+
+                    self.editor_contents.push_str(&welcome);
+                } else {
+                    self.editor_contents.push('~');
+                }
+            } else {
+                if self.config.soft_wrap && start > 0 {
+                    // Wrapped rows only get their number once
+                    (0..gutter_width).for_each(|_| self.editor_contents.push(' '));
+                } else if gutter_width > 0 {
+                    let cursor_y = self.cursor_controller.cursor_y;
+                    let line_number = if self.config.line_numbers == LineNumbers::Relative
+                        && file_row != cursor_y
+                    {
+                        file_row.abs_diff(cursor_y)
+                    } else {
+                        file_row + 1
+                    };
+
+                    let _ = queue!(
+                        self.editor_contents,
+                        SetForegroundColor(theme.line_numbers)
+                    );
+                    self.editor_contents.push_str(&format!(
+                        "{:>width$} ",
+                        line_number,
+                        width = gutter_width - 1
+                    ));
+                    let _ = queue!(self.editor_contents, SetForegroundColor(Color::Reset));
+                }
+
+                self.editor_rows.update_highlight(file_row);
+                let misspelled = if self.config.spell_check {
+                    self.misspelled(file_row)
+                } else {
+                    Vec::new()
+                };
+                let row = self.editor_rows.get_editor_row(file_row);
+                let highlight = row.highlight.as_deref().unwrap_or_default();
+
+                let matches = match &search_keyword {
+                    Some(keyword) => row.find_all(keyword, self.search_index.case_insensitive),
+                    None => Vec::new(),
+                };
+                let selected_columns = self.selection.and_then(|selection| {
+                    selection.row_range(file_row, row).map(|(start_x, end_x)| {
+                        (
+                            row.get_render_x(start_x, self.editor_rows.tab_stop),
+                            row.get_render_x(end_x, self.editor_rows.tab_stop),
+                        )
+                    })
+                });
+                let line_background = if self.config.highlight_current_line
+                    && file_row == self.cursor_controller.cursor_y
+                {
+                    theme.current_line
+                } else {
+                    Color::Reset
+                };
+                // The ruler colors the cell right after the first
+                // `ruler` columns, over the current line's color
+                let ruler = self.config.ruler;
+                let show_whitespace = self.config.show_whitespace;
+                // Without soft wrap, a row running off the left or
+                // right edge of the screen gets a dimmed < or > in
+                // the column at that edge
+                let edge_markers = !self.config.soft_wrap;
+                let continues_left = edge_markers && start > 0;
+                let continues_right = edge_markers && row.render_width() > end;
+                // Render columns of the whitespace ending the row
+                let trailing_whitespace = Some(row)
+                    .filter(|_| {
+                        self.config.highlight_trailing_whitespace
+                            && file_row != self.cursor_controller.cursor_y
+                    })
+                    .map(|row| row.render.trim_end().graphemes(true).count()..row.render_width());
+                let base_background = |column: usize| {
+                    if trailing_whitespace
+                        .as_ref()
+                        .is_some_and(|trailing| trailing.contains(&column))
+                    {
+                        theme.trailing_whitespace
+                    } else if ruler == Some(column) {
+                        theme.ruler
+                    } else {
+                        line_background
+                    }
+                };
+                // Rows with nothing that changes from frame to frame
+                // on them (matches, the selection or the current line)
+                // are drawn once and then copied from the row's cache
+                let cache_key = (
+                    start,
+                    end,
+                    screen_columns,
+                    ruler,
+                    show_whitespace,
+                    edge_markers,
+                    trailing_whitespace.is_some(),
+                );
+                let cacheable = matches.is_empty()
+                    && misspelled.is_empty()
+                    && selected_columns.is_none()
+                    && line_background == Color::Reset;
+                if let Some((_, drawn)) = row
+                    .drawn
+                    .as_ref()
+                    .filter(|(key, _)| cacheable && *key == cache_key)
+                {
+                    self.editor_contents.push_str(drawn);
+                } else {
+                    let mut line = EditorContents::new();
+                    let mut background = Color::Reset;
+                    let mut foreground = Color::Reset;
+                    let mut reversed = false;
+                    let mut underlined = false;
+
+                    row.render
+                        .graphemes(true)
+                        .enumerate()
+                        .skip(start)
+                        .take(end - start)
+                        .for_each(|(column, c)| {
+                            // The match under the cursor gets its own color
+                            let match_background = matches
+                                .iter()
+                                .find(|(start, end)| (*start..*end).contains(&column))
+                                .map_or(base_background(column), |(start, _)| {
+                                    if self.search_index.current_match == Some((file_row, *start)) {
+                                        theme.current_match
+                                    } else {
+                                        theme.search_match
+                                    }
+                                });
+
+                            // The selection is reversed unless the theme
+                            // gives it a background of its own
+                            let selected = selected_columns
+                                .is_some_and(|(start, end)| (start..end).contains(&column));
+                            let match_background = match theme.selection {
+                                Some(selection) if selected => selection,
+                                _ => match_background,
+                            };
+                            let selected = selected && theme.selection.is_none();
+
+                            if match_background != background {
+                                background = match_background;
+                                let _ = queue!(line, SetBackgroundColor(background));
+                            }
+
+                            let misspelled =
+                                misspelled.iter().any(|word| word.contains(&column));
+                            if misspelled != underlined {
+                                underlined = misspelled;
+                                let _ = queue!(
+                                    line,
+                                    SetAttribute(if underlined {
+                                        Attribute::Underlined
+                                    } else {
+                                        Attribute::NoUnderline
+                                    })
+                                );
+                            }
+
+                            if selected != reversed {
+                                reversed = selected;
+                                let _ = queue!(
+                                    line,
+                                    SetAttribute(if reversed {
+                                        Attribute::Reverse
+                                    } else {
+                                        Attribute::NoReverse
+                                    })
+                                );
+                            }
+
+                            // Shown whitespace is drawn dimmed, a tab as an
+                            // arrow followed by its usual padding
+                            let whitespace = match c {
+                                " " if show_whitespace => row
+                                    .tabs
+                                    .iter()
+                                    .find(|(start, end)| (*start..*end).contains(&column))
+                                    .map_or(Some('\u{b7}'), |(start, _)| {
+                                        Some(if *start == column { '\u{2192}' } else { ' ' })
+                                    }),
+                                _ => None,
+                            };
+
+                            // A wide character under a marker is covered up
+                            // whole, its filler is skipped like always
+                            let marker = match c {
+                                _ if continues_left && column == start => Some('<'),
+                                WIDE_FILLER => None,
+                                _ if continues_right && column + grapheme_width(c) >= end => {
+                                    Some('>')
+                                }
+                                _ => None,
+                            };
+
+                            let color = if whitespace.is_some() || marker.is_some() {
+                                theme.dim
+                            } else {
+                                highlight
+                                    .get(column)
+                                    .map_or(Color::Reset, |kind| kind.color(&theme))
+                            };
+                            if color != foreground {
+                                foreground = color;
+                                let _ = queue!(line, SetForegroundColor(foreground));
+                            }
+
+                            // Half of a wide character cut off by the edge
+                            // of the screen is drawn as a space
+                            let covered = column + 1 < end && grapheme_width(c) == 2;
+                            match c {
+                                _ if marker == Some('<') => {
+                                    line.push('<');
+                                    if covered {
+                                        line.push(' ');
+                                    }
+                                }
+                                _ if marker == Some('>') => {
+                                    if covered {
+                                        line.push(' ');
+                                    }
+                                    line.push('>');
+                                }
+                                WIDE_FILLER if column == start => line.push(' '),
+                                WIDE_FILLER => {}
+                                _ if column + 1 == end && grapheme_width(c) == 2 => line.push(' '),
+                                _ => match whitespace {
+                                    Some(whitespace) => line.push(whitespace),
+                                    None => line.push_str(c),
+                                },
+                            }
+                        });
+                    if underlined {
+                        let _ = queue!(line, SetAttribute(Attribute::NoUnderline));
+                    }
+
+                    // Past the end of the row, the current line's
+                    // background is padded out to the edge of the
+                    // screen and the ruler still gets drawn
+                    let drawn = cmp::min(row.render_width().saturating_sub(start), end - start);
+                    let padding = if line_background != Color::Reset {
+                        screen_columns
+                    } else {
+                        ruler
+                            .filter(|&column| {
+                                column >= start + drawn && column < start + screen_columns
+                            })
+                            .map_or(0, |column| column - start + 1)
+                            .min(screen_columns)
+                    };
+                    if padding > drawn && reversed {
+                        reversed = false;
+                        let _ = queue!(line, SetAttribute(Attribute::NoReverse));
+                    }
+                    (drawn..padding).for_each(|screen_column| {
+                        let padding_background = base_background(screen_column + start);
+                        if padding_background != background {
+                            background = padding_background;
+                            let _ = queue!(line, SetBackgroundColor(background));
+                        }
+                        line.push(' ');
+                    });
+
+                    if foreground != Color::Reset {
+                        let _ = queue!(line, SetForegroundColor(Color::Reset));
+                    }
+                    if background != Color::Reset {
+                        let _ = queue!(line, SetBackgroundColor(Color::Reset));
+                    }
+                    if reversed {
+                        let _ = queue!(line, SetAttribute(Attribute::NoReverse));
+                    }
+
+                    self.editor_contents.push_str(&line.content);
+                    if cacheable {
+                        self.editor_rows.get_editor_row_mut(file_row).drawn =
+                            Some((cache_key, line.content));
+                    }
+                }
+
+                // self.editor_contents.push_str(&row[start..start + len]);
+            }
+            if reaches_right_edge {
+                queue!(
+                    self.editor_contents,
+                    terminal::Clear(ClearType::UntilNewLine)
+                )
+                .unwrap();
+            }
+            if self.config.scrollbar && self.win_size.0 > 0 {
+                let (ch, color) = if scrollbar_thumb.contains(&i) {
+                    ('\u{2588}', theme.scrollbar)
+                } else {
+                    ('\u{2502}', theme.dim)
+                };
+                let _ = queue!(
+                    self.editor_contents,
+                    cursor::MoveTo(x_offset + self.win_size.0 as u16 - 1, i as u16),
+                    SetForegroundColor(color)
+                );
+                self.editor_contents.push(ch);
+                let _ = queue!(self.editor_contents, SetForegroundColor(Color::Reset));
+            }
+            self.editor_contents.end_line();
+        }
+    }
+
+    fn draw_status_bar(&mut self) {
+        self.editor_contents
+            .start_line(self.x_offset as u16, self.win_size.1 as u16);
+        let theme = self.config.theme.fit(self.config.color_depth);
+        let flashing = self
+            .flashed_at
+            .is_some_and(|at| at.elapsed() < BELL_FLASH);
+        if !flashing {
+            self.flashed_at = None;
+        }
+        if flashing {
+            let _ = queue!(
+                self.editor_contents,
+                SetBackgroundColor(theme.bell),
+                SetForegroundColor(Color::White)
+            );
+        } else if theme.status_bar.is_none() && theme.status_bar_text.is_none() {
+            self.editor_contents
+                .push_str(&style::Attribute::Reverse.to_string());
+        } else {
+            let _ = queue!(
+                self.editor_contents,
+                SetBackgroundColor(theme.status_bar.unwrap_or(Color::Reset)),
+                SetForegroundColor(theme.status_bar_text.unwrap_or(Color::Reset))
+            );
+        }
+
+        let info = format!(
+            "{}{}{}{} {} -- {} lines",
+            match self.mode {
+                _ if !self.config.modal => "",
+                Mode::Normal => "NORMAL | ",
+                Mode::Insert => "INSERT | ",
+            },
+            if self.buffer_position.1 > 1 {
+                format!("[{}/{}] ", self.buffer_position.0, self.buffer_position.1)
+            } else {
+                String::new()
+            },
+            self.editor_rows.display_name(),
+            if self.read_only { " [RO]" } else { "" },
+            if self.dirty > 0 { "(modified)" } else { "" },
+            self.editor_rows.number_of_rows()
+        );
+
+        let info: String = info.chars().take(self.win_size.0).collect();
+        let info_len = info.chars().count();
+
+        // The render column is shown too when tabs put it
+        // somewhere other than the character column
+        let column = self.cursor_controller.cursor_x + 1;
+        let render_column = self.cursor_controller.render_x + 1;
+        let line_info = if render_column == column {
+            format!("Ln {}, Col {}", self.cursor_controller.cursor_y + 1, column)
+        } else {
+            format!(
+                "Ln {}, Col {}-{}",
+                self.cursor_controller.cursor_y + 1,
+                column,
+                render_column
+            )
+        };
+
+        // The right side segments are dropped starting from
+        // the left one until they fit next to the file info
+        let mut segments = vec![
+            self.editor_rows.syntax.file_type().to_string(),
+            "utf-8".to_string(),
+            self.editor_rows.line_ending.name().to_string(),
+            line_info,
+        ];
+        if let Some(match_count) = self.search_index.match_count() {
+            segments.insert(segments.len() - 1, match_count);
+        }
+        let mut right_info = segments.join(" | ");
+        while !segments.is_empty() && info_len + right_info.chars().count() > self.win_size.0 {
+            segments.remove(0);
+            right_info = segments.join(" | ");
+        }
+
+        self.editor_contents.push_str(&info);
+        (info_len..self.win_size.0 - right_info.chars().count())
+            .for_each(|_| self.editor_contents.push(' '));
+        self.editor_contents.push_str(&right_info);
+
+        self.editor_contents
+            .push_str(&style::Attribute::Reset.to_string());
+        if !self.reaches_right_edge() {
+            self.editor_contents.push('\u{2502}');
+        }
+        self.editor_contents.end_line();
+    }
+
+    fn draw_message_bar(&mut self) {
+        // Draws out any message passed in at the very bottom
+        // of the screen, across the whole width even when
+        // the screen is split
+        let message_bar_row = self.terminal_size.1.saturating_sub(1) as u16;
+        self.editor_contents.start_line(0, message_bar_row);
+        queue!(
+            self.editor_contents,
+            terminal::Clear(ClearType::UntilNewLine)
+        )
+        .unwrap();
+
+        let prefix = self.message_prefix();
+        if let Some(msg) = self.status_message.message() {
+            let msg = format!("{}{}", prefix, msg);
+            let msg: String = msg.chars().take(self.terminal_size.0).collect();
+            self.editor_contents.push_str(&msg);
+        }
+        self.editor_contents.end_line();
+    }
+
+    fn message_prefix(&self) -> &'static str {
+        if self.search_index.active && self.search_index.case_insensitive {
+            "[case insensitive] "
+        } else {
+            ""
+        }
+    }
+
+    // Scrolls and draws the rows and status bar of the
+    // buffer's pane
+    fn draw_pane(&mut self) {
+        let taken = self.gutter_width() + self.scrollbar_width();
+        self.cursor_controller.screen_columns = self.win_size.0.saturating_sub(taken);
+        self.cursor_controller.soft_wrap = self.config.soft_wrap;
+        self.cursor_controller.scroll(&self.editor_rows, self.config.scrolloff);
+
+        self.draw_rows();
+        // A terminal too short for both bars only gets the
+        // message bar, since prompts are shown there
+        if self.terminal_size.1 >= 2 {
+            self.draw_status_bar();
+        }
+    }
+
+    // Draws the pane of a buffer shown in a split without
+    // having the focus
+    fn draw_unfocused(&mut self) -> io::Result<()> {
+        queue!(self.editor_contents, cursor::Hide)?;
+        self.draw_pane();
+        self.editor_contents.flush()
+    }
+
+    fn refresh_screen(&mut self) -> io::Result<()> {
+        // 'queue' will queue commands to be run in the terminal
+        // (provided by crossterm)
+        // Hide the cursor before updates and relocate it to the top left
+        // Show it back when update finishes
+        // Also calls scroll
+        queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
+
+        self.draw_pane();
+        if self.terminal_size.1 >= 1 {
+            self.draw_message_bar();
+        }
+
+        // Move the cursor to particular location based on
+        // the cursor controller class
+        let (cursor_x, cursor_y) = match self.prompt_cursor {
+            Some(column) => (
+                cmp::min(
+                    self.message_prefix().len() + column,
+                    self.terminal_size.0.saturating_sub(1),
+                ),
+                self.terminal_size.1.saturating_sub(1),
+            ),
+            None => {
+                let (cursor_x, cursor_y) =
+                    self.cursor_controller.screen_position(&self.editor_rows);
+                (cursor_x + self.gutter_width() + self.x_offset, cursor_y)
+            }
+        };
+
+        queue!(
+            self.editor_contents,
+            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+            cursor::Show,
+        )?;
+        self.editor_contents.flush()
+    }
+
+    pub fn move_cursor(&mut self, direction: KeyCode) {
+        self.cursor_controller
+            .move_cursor(direction, &self.editor_rows);
+    }
+
+    fn match_bracket(&mut self) {
+        if !self.cursor_controller.match_bracket(&self.editor_rows) {
+            self.status_message
+                .set_message("No matching bracket".into());
+            self.ring_bell();
+        }
+    }
+
+    // Render columns of the misspelled words on row at, worked
+    // out again only once the row or the dictionary changed.
+    // A word list that can't be read turns spell check off
+    fn misspelled(&mut self, at: usize) -> Vec<Range<usize>> {
+        let path = self.config.dictionary.clone();
+        let row = self.editor_rows.get_editor_row_mut(at);
+        let found = Dictionary::with(&path, |dictionary| match &row.misspelled {
+            Some((words, misspelled)) if *words == dictionary.words.len() => misspelled.clone(),
+            _ => {
+                let misspelled = dictionary.misspelled(&row.render);
+                row.misspelled = Some((dictionary.words.len(), misspelled.clone()));
+                misspelled
+            }
+        });
+        match found {
+            Ok(misspelled) => misspelled,
+            Err(err) => {
+                self.config.spell_check = false;
+                self.status_message.set_message(format!("Spell check off, {}", err));
+                Vec::new()
+            }
+        }
+    }
+
+    // Alt+A adds the word under the cursor to the words file,
+    // so spell check stops marking it
+    fn add_word(&mut self) {
+        let (cursor_x, cursor_y) = self.cursor_controller.position();
+        let chars: Vec<char> = if cursor_y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_editor_row(cursor_y).row_content.chars().collect()
+        } else {
+            Vec::new()
+        };
+        let is_letter = |ch: &char| ch.is_alphabetic() || *ch == '\'';
+        let start = chars[..cursor_x.min(chars.len())]
+            .iter()
+            .rposition(|ch| !is_letter(ch))
+            .map_or(0, |index| index + 1);
+        let end = chars[start..]
+            .iter()
+            .position(|ch| !is_letter(ch))
+            .map_or(chars.len(), |length| start + length);
+        let word: String = chars[start..end].iter().collect();
+        let word = word.trim_matches('\'');
+        if word.is_empty() {
+            self.status_message
+                .set_message("No word under the cursor".into());
+            self.ring_bell();
+            return;
+        }
+
+        let message = match Dictionary::with(&self.config.dictionary, |dictionary| {
+            dictionary.add(word)
+        }) {
+            Ok(Ok(())) => format!("Added {} to the dictionary", word),
+            Ok(Err(err)) => format!("Couldn't add {}: {}", word, err),
+            Err(err) => format!("Couldn't read {}", err),
+        };
+        self.status_message.set_message(message);
+    }
+
+    // Tells the user a key couldn't do anything, the way the
+    // config asks for. The flash or bell goes out with the
+    // next frame
+    fn ring_bell(&mut self) {
+        match self.config.bell {
+            Bell::Off => {}
+            Bell::Visual => self.flashed_at = Some(Instant::now()),
+            Bell::Audible => self.editor_contents.push('\x07'),
+        }
+    }
+}
+
+// Runs a formatter command with the text on its stdin and
+// returns what it prints. The command is split on whitespace,
+// it isn't run through a shell
+fn run_formatter(command: &str, input: String) -> Result<String, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("the formatter command is empty")?;
+    let mut child = process::Command::new(program)
+        .args(words)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{}: {}", program, err))?;
+
+    // Written from another thread so a formatter that prints
+    // before it has read everything can't block on a full pipe
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => line.trim().to_string(),
+            None => format!("{} exited with {}", program, output.status),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("{} printed invalid UTF-8", program))
+}
+
+// Reader struct is used to read keypresses by the user. Every
+// key goes through it, including the ones answering prompts,
+// so it's also where macros are recorded and played back
+struct Reader {
+    // Keys read so far while a macro is being recorded
+    recording: Option<Vec<KeyEvent>>,
+    // The last macro recorded
+    register: Vec<KeyEvent>,
+    // Keys of the macro being played, read before the terminal
+    playback: VecDeque<KeyEvent>,
+    // Whether the last key read came from playback
+    played: bool,
+    // Where the keys come from, usually the terminal
+    keys: Box<dyn KeyProvider>,
+}
+
+impl Reader {
+    fn new() -> Self {
+        Self::with_keys(Box::new(TerminalKeys))
+    }
+
+    fn with_keys(keys: Box<dyn KeyProvider>) -> Self {
+        Self {
+            recording: None,
+            register: Vec::new(),
+            playback: VecDeque::new(),
+            played: false,
+            keys,
+        }
+    }
+
+    fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    // Keeps what was recorded as the macro, without the key
+    // that stopped the recording. Returns how many keys it has
+    fn stop_recording(&mut self) -> Option<usize> {
+        let mut keys = self.recording.take()?;
+        keys.pop();
+        self.register = keys;
+        Some(self.register.len())
+    }
+
+    // Queues the macro to be read in place of the terminal.
+    // Nothing is played from a macro that is being played,
+    // so a macro can't end up playing itself forever
+    fn play(&mut self) {
+        if !self.played {
+            self.playback = self.register.iter().copied().collect();
+        }
+    }
+
+    // Whether something can be read without waiting
+    fn has_input(&mut self) -> io::Result<bool> {
+        Ok(!self.playback.is_empty() || self.keys.key_pressed()?)
+    }
+
+    // Read the key pressed by the user (or a paste, a mouse
+    // event or a resize of the terminal). Nothing is returned
+    // if none of them happens within timeout
+    fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        self.played = false;
+        if let Some(key_event) = self.playback.pop_front() {
+            // Pressing any key stops the playback, that key
+            // is then read like any other
+            if !self.keys.key_pressed()? {
+                self.played = true;
+                return Ok(Some(Event::Key(key_event)));
+            }
+            self.playback.clear();
+        }
+
+        let event = self.keys.read_event(timeout)?;
+        if let (Some(keys), Some(Event::Key(key_event))) = (&mut self.recording, &event) {
+            keys.push(*key_event);
+        }
+        Ok(event)
+    }
+}
+
+// Where the editor's events come from. The terminal is the
+// usual one, a script or a replayed file can stand in for it
+pub trait KeyProvider {
+    // The next key press, paste, mouse event or resize, None
+    // if nothing happens within timeout
+    fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>>;
+    // Whether a key was pressed that hasn't been read yet,
+    // this is what stops a macro being played
+    fn key_pressed(&mut self) -> io::Result<bool>;
+}
+
+struct TerminalKeys;
+
+impl KeyProvider for TerminalKeys {
+    // Checks every 5 seconds for input until timeout
+    fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        let start = Instant::now();
+        loop {
+            let poll_for = match timeout {
+                Some(timeout) => match timeout.checked_sub(start.elapsed()) {
+                    Some(remaining) => cmp::min(remaining, Duration::from_millis(5000)),
+                    None => return Ok(None),
+                },
+                None => Duration::from_millis(5000),
+            };
+            if event::poll(poll_for)? {
+                match event::read()? {
+                    // Only presses are wanted where terminals
+                    // report releases too
+                    Event::Key(key_event) if key_event.kind == KeyEventKind::Release => {}
+                    event @ (Event::Key(_)
+                    | Event::Resize(..)
+                    | Event::Paste(_)
+                    | Event::Mouse(_)) => return Ok(Some(event)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn key_pressed(&mut self) -> io::Result<bool> {
+        event::poll(Duration::ZERO)
+    }
+}
+
+// Events given up front, to drive the editor from tests.
+// Reading past the last one is an UnexpectedEof error, so
+// a test can't hang waiting for more
+pub struct ScriptedKeys {
+    events: VecDeque<Event>,
+}
+
+impl ScriptedKeys {
+    pub fn new(events: Vec<Event>) -> Self {
+        Self {
+            events: events.into(),
+        }
+    }
+}
+
+impl KeyProvider for ScriptedKeys {
+    fn read_event(&mut self, _timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        match self.events.pop_front() {
+            Some(event) => Ok(Some(event)),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the script ran out of events",
+            )),
+        }
+    }
+
+    // Every key of a script is pressed after the one before
+    // it was handled, so none of them stops a macro
+    fn key_pressed(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+// Time between the keys of a replayed file, slow enough to
+// watch them being pressed
+const REPLAY_DELAY: Duration = Duration::from_millis(100);
+
+// Keys read from the file given with --replay, for demos and
+// for showing how to get to a bug. The file has a key on each
+// line, written like in the key_bindings of the config, and
+// lines starting with " type out the rest of the line. Blank
+// lines and lines starting with # are skipped. Once the keys
+// run out the terminal takes over
+struct ReplayedKeys {
+    keys: VecDeque<KeyEvent>,
+}
+
+impl ReplayedKeys {
+    fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read replay file {}: {}", path, err))?;
+        let mut keys = VecDeque::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if let Some(text) = line.strip_prefix('"') {
+                keys.extend(text.chars().map(|ch| KeyEvent::from(KeyCode::Char(ch))));
+                continue;
+            }
+            let mut chars = line.chars();
+            let (code, modifiers) = match (chars.next(), chars.next()) {
+                // A single character is typed as it is, keeping its case
+                (Some(ch), None) => (KeyCode::Char(ch), KeyModifiers::NONE),
+                (None, _) | (Some('#'), _) => continue,
+                _ => parse_key_chord(line).ok_or_else(|| {
+                    format!("Replay file error: unknown key {} on line {}", line, index + 1)
+                })?,
+            };
+            keys.push_back(KeyEvent::new(code, modifiers));
+        }
+        Ok(Self { keys })
+    }
+}
+
+impl KeyProvider for ReplayedKeys {
+    fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        match self.keys.pop_front() {
+            Some(key_event) => {
+                thread::sleep(REPLAY_DELAY);
+                Ok(Some(Event::Key(key_event)))
+            }
+            None => TerminalKeys.read_event(timeout),
+        }
+    }
+
+    fn key_pressed(&mut self) -> io::Result<bool> {
+        TerminalKeys.key_pressed()
+    }
+}
+
+// Groups characters for word movement, a word is a
+// run of characters of the same class
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    fn of(ch: char) -> Self {
+        if ch.is_whitespace() {
+            CharClass::Whitespace
+        } else if ch.is_alphanumeric() || ch == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+// What a rendered character is colored as
+#[derive(Clone, Copy, PartialEq)]
+enum HighlightKind {
+    Normal,
+    Number,
+    String,
+    Comment,
+    Keyword,
+}
+
+impl HighlightKind {
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            HighlightKind::Normal => Color::Reset,
+            HighlightKind::Number => theme.number,
+            HighlightKind::String => theme.string,
+            HighlightKind::Comment => theme.comment,
+            HighlightKind::Keyword => theme.keyword,
+        }
+    }
+}
+
+// A language's highlighting rules. highlight() classifies
+// every character of a row's render string
+trait SyntaxHighlight {
+    fn file_type(&self) -> &'static str;
+    fn extensions(&self) -> &'static [&'static str];
+    // What starts a comment running to the end of the line
+    fn line_comment(&self) -> &'static str;
+    fn highlight(&self, render: &str) -> Vec<HighlightKind>;
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The tokenizer helpers below take the index a token starts
+// at and return the index just past its end
+
+// Strings end at the next unescaped quote matching the
+// opening one, or at the end of the row
+fn string_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != chars[start] {
+        i += if chars[i] == '\\' { 2 } else { 1 };
+    }
+    cmp::min(i + 1, chars.len())
+}
+
+// Numbers take in the rest of the word (for hex digits and
+// suffixes like 0xff or 10u8) and a decimal point
+fn number_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len()
+        && (is_word_char(chars[i])
+            || chars[i] == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+    {
+        i += 1;
+    }
+    i
+}
+
+fn word_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() && is_word_char(chars[i]) {
+        i += 1;
+    }
+    i
+}
+
+// Used for files of no known type. Colors numbers, double
+// quoted strings and comments starting with # or //, digits
+// that are part of a word or a string aren't numbers
+struct PlainHighlight;
+
+impl SyntaxHighlight for PlainHighlight {
+    fn file_type(&self) -> &'static str {
+        "plain"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn line_comment(&self) -> &'static str {
+        "#"
+    }
+
+    fn highlight(&self, render: &str) -> Vec<HighlightKind> {
+        let chars: Vec<char> = render.chars().collect();
+        let mut highlight = vec![HighlightKind::Normal; chars.len()];
+        let mut i = 0;
+
+        while i < chars.len() {
+            let start = i;
+            // Comment markers only count at the start of a word
+            // so something like http://example.com isn't one
+            let after_space = i == 0 || chars[i - 1].is_whitespace();
+            let kind = match chars[i] {
+                '#' if after_space => {
+                    i = chars.len();
+                    HighlightKind::Comment
+                }
+                '/' if after_space && chars.get(i + 1) == Some(&'/') => {
+                    i = chars.len();
+                    HighlightKind::Comment
+                }
+                '"' => {
+                    i = string_end(&chars, i);
+                    HighlightKind::String
+                }
+                c if c.is_ascii_digit() => {
+                    i = number_end(&chars, i);
+                    HighlightKind::Number
+                }
+                c if is_word_char(c) => {
+                    i = word_end(&chars, i);
+                    HighlightKind::Normal
+                }
+                _ => {
+                    i += 1;
+                    HighlightKind::Normal
+                }
+            };
+            highlight[start..i].fill(kind);
+        }
+
+        highlight
+    }
+}
+
+struct RustHighlight;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while",
+];
+
+impl SyntaxHighlight for RustHighlight {
+    fn file_type(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rs"]
+    }
+
+    fn line_comment(&self) -> &'static str {
+        "//"
+    }
+
+    fn highlight(&self, render: &str) -> Vec<HighlightKind> {
+        let chars: Vec<char> = render.chars().collect();
+        let mut highlight = vec![HighlightKind::Normal; chars.len()];
+        let mut i = 0;
+
+        while i < chars.len() {
+            let start = i;
+            let kind = match chars[i] {
+                '/' if chars.get(i + 1) == Some(&'/') => {
+                    i = chars.len();
+                    HighlightKind::Comment
+                }
+                '"' => {
+                    i = string_end(&chars, i);
+                    HighlightKind::String
+                }
+                // A quote is a char literal like 'a' or '\n',
+                // otherwise it starts a lifetime
+                '\'' if chars.get(i + 1) == Some(&'\\') => {
+                    i = string_end(&chars, i);
+                    HighlightKind::String
+                }
+                '\'' if chars.get(i + 2) == Some(&'\'') => {
+                    i += 3;
+                    HighlightKind::String
+                }
+                c if c.is_ascii_digit() => {
+                    i = number_end(&chars, i);
+                    HighlightKind::Number
+                }
+                c if is_word_char(c) => {
+                    i = word_end(&chars, i);
+                    let word: String = chars[start..i].iter().collect();
+                    if RUST_KEYWORDS.contains(&word.as_str()) {
+                        HighlightKind::Keyword
+                    } else {
+                        HighlightKind::Normal
+                    }
+                }
+                _ => {
+                    i += 1;
+                    HighlightKind::Normal
+                }
+            };
+            highlight[start..i].fill(kind);
+        }
+
+        highlight
+    }
+}
+
+const PLAIN_HIGHLIGHT: PlainHighlight = PlainHighlight;
+const SYNTAXES: &[&dyn SyntaxHighlight] = &[&RustHighlight];
+
+// Picks the highlighting rules from the file extension
+fn syntax_for(filename: Option<&Path>) -> &'static dyn SyntaxHighlight {
+    filename
+        .and_then(|name| name.extension())
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| {
+            SYNTAXES
+                .iter()
+                .find(|syntax| syntax.extensions().contains(&extension))
+                .copied()
+        })
+        .unwrap_or(&PLAIN_HIGHLIGHT)
+}
+
+// Modes of vim-like modal editing. In Normal mode letters
+// are commands, Insert mode types them like without
+// modal editing
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Normal,
+    Insert,
+}
+
+// The part of the terminal a buffer is drawn in, the
+// screen can be split into a left and a right pane
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Full,
+    Left,
+    Right,
+}
+
+// Wide characters (most CJK and emoji) take two cells of the
+// terminal. In a row's render they're followed by this, so that
+// every grapheme cluster of the render is still one screen column
+const WIDE_FILLER: &str = "\0";
+
+// Columns a grapheme cluster other than a tab takes on screen.
+// Zero width ones are counted as one like before
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().clamp(1, 2)
+}
+
+// The words spell check knows: the word list from the config
+// and the ones added to the words file, all lowercased
+struct Dictionary {
+    path: PathBuf,
+    words: HashSet<String>,
+}
+
+// Loaded the first time spell check needs it, and shared by
+// every buffer. Err holds why the word list couldn't be read
+static DICTIONARY: Mutex<Option<Result<Dictionary, String>>> = Mutex::new(None);
+
+impl Dictionary {
+    fn load(path: &Path) -> Result<Self, String> {
+        let mut words: HashSet<String> = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?
+            .lines()
+            .map(|word| word.trim().to_lowercase())
+            .collect();
+        if let Some(added) = Self::words_file().and_then(|file| fs::read_to_string(file).ok()) {
+            words.extend(added.lines().map(|word| word.trim().to_lowercase()));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            words,
+        })
+    }
+
+    // Where words added with Alt+A are kept
+    fn words_file() -> Option<PathBuf> {
+        Config::path().map(|path| path.with_file_name("words"))
+    }
+
+    // Runs f with the dictionary, loading it first if it isn't
+    // yet or the config now names another word list
+    fn with<T>(path: &Path, f: impl FnOnce(&mut Self) -> T) -> Result<T, String> {
+        let mut dictionary = DICTIONARY.lock().unwrap_or_else(PoisonError::into_inner);
+        let loaded = match &*dictionary {
+            Some(Ok(dictionary)) => dictionary.path == path,
+            Some(Err(_)) | None => false,
+        };
+        if !loaded {
+            *dictionary = Some(Self::load(path));
+        }
+        match dictionary.as_mut() {
+            Some(Ok(dictionary)) => Ok(f(dictionary)),
+            Some(Err(err)) => Err(err.clone()),
+            None => unreachable!(),
+        }
+    }
+
+    // A word ending in 's is also known when the rest of it is
+    fn knows(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        self.words.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    fn add(&mut self, word: &str) -> io::Result<()> {
+        let path =
+            Self::words_file().ok_or_else(|| io::Error::other("there's no home directory"))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", word)?;
+        self.words.insert(word.to_lowercase());
+        Ok(())
+    }
+
+    // Render columns of the words of render it doesn't know.
+    // Words are runs of letters and apostrophes, runs with
+    // digits or underscores in them (identifiers, mostly)
+    // aren't checked
+    fn misspelled(&self, render: &str) -> Vec<Range<usize>> {
+        let graphemes: Vec<&str> = render.graphemes(true).collect();
+        let is_word = |grapheme: &str| {
+            grapheme_width(grapheme) == 1
+                && grapheme
+                    .chars()
+                    .next()
+                    .is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '\'')
+        };
+
+        let mut misspelled = Vec::new();
+        let mut column = 0;
+        while column < graphemes.len() {
+            if !is_word(graphemes[column]) {
+                column += 1;
+                continue;
+            }
+            let run_end = graphemes[column..]
+                .iter()
+                .position(|grapheme| !is_word(grapheme))
+                .map_or(graphemes.len(), |length| column + length);
+            // Quotes around the word aren't part of it
+            let mut start = column;
+            let mut end = run_end;
+            while start < end && graphemes[start] == "'" {
+                start += 1;
+            }
+            while end > start && graphemes[end - 1] == "'" {
+                end -= 1;
+            }
+            let word = graphemes[start..end].concat();
+            if !word.is_empty()
+                && word.chars().all(|ch| ch.is_alphabetic() || ch == '\'')
+                && !self.knows(&word)
+            {
+                misspelled.push(start..end);
+            }
+            column = run_end;
+        }
+        misspelled
+    }
+}
+
+// What a drawn row depends on besides its contents: the range
+// of render columns drawn, the width of the screen, the ruler,
+// whether whitespace is shown, whether the markers for rows
+// continuing off screen are drawn and whether trailing
+// whitespace is highlighted
+type DrawnKey = (usize, usize, usize, Option<usize>, bool, bool, bool);
+
+// Used to store row content and row render
+// content
+#[derive(Default)]
+pub struct Row {
+    row_content: String,
+    render: String,
+    // Highlight of each column of render, cleared whenever
+    // the row is re-rendered and recomputed when it is drawn
+    highlight: Option<Vec<HighlightKind>>,
+    // Render columns (start, end) each tab was expanded to,
+    // so they can be told apart from typed spaces
+    tabs: Vec<(usize, usize)>,
+    // The row as last drawn and what it was drawn for. None
+    // marks the row as dirty, it's cleared along with highlight
+    drawn: Option<(DrawnKey, String)>,
+    // Render columns of the misspelled words, along with the
+    // number of words the dictionary had when they were found
+    misspelled: Option<(usize, Vec<Range<usize>>)>,
+}
+
+impl Row {
+    pub fn new(row_content: String, render: String) -> Self {
+        Self {
+            row_content,
+            render,
+            highlight: None,
+            tabs: Vec::new(),
+            drawn: None,
+            misspelled: None,
+        }
+    }
+
+    // Number of characters in the row. cursor_x counts
+    // characters, so this is what it's clamped against
+    fn len(&self) -> usize {
+        self.row_content.chars().count()
+    }
+
+    // The text of the row as it is in the file
+    pub fn content(&self) -> &str {
+        &self.row_content
+    }
+
+    // Character index each grapheme cluster starts at, then the
+    // length of the row. The cursor only moves between these so
+    // a letter and its accents are never split
+    fn grapheme_boundaries(&self) -> Vec<usize> {
+        let mut x = 0;
+        let mut boundaries: Vec<usize> = self
+            .row_content
+            .graphemes(true)
+            .map(|grapheme| {
+                let start = x;
+                x += grapheme.chars().count();
+                start
+            })
+            .collect();
+        boundaries.push(x);
+        boundaries
+    }
+
+    fn previous_boundary(&self, x: usize) -> usize {
+        self.grapheme_boundaries()
+            .into_iter()
+            .rev()
+            .find(|&boundary| boundary < x)
+            .unwrap_or(0)
+    }
+
+    fn next_boundary(&self, x: usize) -> usize {
+        self.grapheme_boundaries()
+            .into_iter()
+            .find(|&boundary| boundary > x)
+            .unwrap_or_else(|| self.len())
+    }
+
+    // Columns the render takes on screen
+    fn render_width(&self) -> usize {
+        self.render.graphemes(true).count()
+    }
+
+    // Converts a character index (like cursor_x) into the
+    // byte offset String methods expect
+    fn byte_index(&self, at: usize) -> usize {
+        char_byte_index(&self.row_content, at)
+    }
+
+    fn insert_char(&mut self, at: usize, ch: char, tab_stop: usize) {
+        let at = self.byte_index(at);
+        self.row_content.insert(at, ch);
+        EditorRows::render_row(self, tab_stop);
+    }
+
+    // Removes the grapheme cluster starting at character `at`
+    // and returns it
+    fn delete_char(&mut self, at: usize, tab_stop: usize) -> String {
+        let start = self.byte_index(at);
+        let end = self.byte_index(self.next_boundary(at));
+        let deleted = self.row_content[start..end].to_string();
+        self.row_content.replace_range(start..end, "");
+        EditorRows::render_row(self, tab_stop);
+        deleted
+    }
+
+    fn insert_str(&mut self, at: usize, string: &str, tab_stop: usize) {
+        let at = self.byte_index(at);
+        self.row_content.insert_str(at, string);
+        EditorRows::render_row(self, tab_stop);
+    }
+
+    fn delete_str(&mut self, at: usize, len: usize, tab_stop: usize) {
+        let start = self.byte_index(at);
+        let end = self.byte_index(at + len);
+        self.row_content.replace_range(start..end, "");
+        EditorRows::render_row(self, tab_stop);
+    }
+
+    // Builds the text searched by find (lowercased for case
+    // insensitive searches) along with the byte offset and
+    // column of each of its characters in the original text,
+    // since lowercasing can change the length of a character.
+    // Characters get the column of the grapheme cluster they're
+    // part of, `columns` is how many columns a cluster counts
+    // as, and a last offset marks the end of the text
+    fn search_text(
+        source: &str,
+        case_insensitive: bool,
+        columns: fn(&str) -> usize,
+    ) -> (String, Vec<(usize, usize)>) {
+        let mut text = String::with_capacity(source.len());
+        let mut offsets = Vec::with_capacity(source.len() + 1);
+        let mut column = 0;
+
+        for grapheme in source.graphemes(true) {
+            for ch in grapheme.chars().filter(|_| grapheme != WIDE_FILLER) {
+                if case_insensitive {
+                    ch.to_lowercase().for_each(|lower| {
+                        offsets.push((text.len(), column));
+                        text.push(lower);
+                    });
+                } else {
+                    offsets.push((text.len(), column));
+                    text.push(ch);
+                }
+            }
+            column += columns(grapheme);
+        }
+        offsets.push((text.len(), column));
+
+        (text, offsets)
+    }
+
+    // The render has one column per grapheme cluster
+    fn render_search_text(&self, case_insensitive: bool) -> (String, Vec<(usize, usize)>) {
+        Self::search_text(&self.render, case_insensitive, |_| 1)
+    }
+
+    // Returns the render column of the first match of keyword
+    // starting at render column `from`
+    fn find(&self, keyword: &str, from: usize, case_insensitive: bool) -> Option<usize> {
+        let (text, offsets) = self.render_search_text(case_insensitive);
+        let start = offsets
+            .iter()
+            .find(|(_, column)| *column >= from)
+            .map_or(text.len(), |(offset, _)| *offset);
+
+        text[start..]
+            .find(keyword)
+            .map(|index| Self::search_column(&offsets, start + index))
+    }
+
+    // Returns the render column of the last match of keyword
+    // that starts before render column `before`
+    fn rfind(&self, keyword: &str, before: usize, case_insensitive: bool) -> Option<usize> {
+        let (text, offsets) = self.render_search_text(case_insensitive);
+        let end = offsets
+            .iter()
+            .find(|(_, column)| *column >= before)
+            .map_or(text.len(), |(offset, _)| *offset);
+
+        text[..end]
+            .rfind(keyword)
+            .map(|index| Self::search_column(&offsets, index))
+    }
+
+    // Returns the start and end render columns of every
+    // match of keyword in the row
+    fn find_all(&self, keyword: &str, case_insensitive: bool) -> Vec<(usize, usize)> {
+        let (text, offsets) = self.render_search_text(case_insensitive);
+        let mut matches = Vec::new();
+        let mut start = 0;
+
+        if keyword.is_empty() {
+            return matches;
+        }
+
+        while let Some(index) = text[start..].find(keyword) {
+            let begin = start + index;
+            let end = begin + keyword.len();
+
+            matches.push((
+                Self::search_column(&offsets, begin),
+                Self::search_end_column(&offsets, end),
+            ));
+            start = end;
+        }
+
+        matches
+    }
+
+    // Like find, but searches row_content and returns the start
+    // and end cursor_x of the match so it can be edited
+    fn find_in_content(
+        &self,
+        keyword: &str,
+        from: usize,
+        case_insensitive: bool,
+    ) -> Option<(usize, usize)> {
+        let (text, offsets) = Self::search_text(&self.row_content, case_insensitive, |grapheme| {
+            grapheme.chars().count()
+        });
+        let start = offsets
+            .iter()
+            .find(|(_, column)| *column >= from)
+            .map_or(text.len(), |(offset, _)| *offset);
+
+        if keyword.is_empty() {
+            return None;
+        }
+
+        text[start..].find(keyword).map(|index| {
+            (
+                Self::search_column(&offsets, start + index),
+                Self::search_end_column(&offsets, start + index + keyword.len()),
+            )
+        })
+    }
+
+    fn search_column(offsets: &[(usize, usize)], offset: usize) -> usize {
+        offsets
+            .binary_search_by_key(&offset, |(offset, _)| *offset)
+            .map_or(0, |index| offsets[index].1)
+    }
+
+    // Column just past the end of a match ending at offset
+    fn search_end_column(offsets: &[(usize, usize)], offset: usize) -> usize {
+        offsets
+            .iter()
+            .find(|(start, _)| *start >= offset)
+            .map_or(0, |(_, column)| *column)
+    }
+
+    // Skips back over any whitespace and then the word
+    // before it, returning where that word starts
+    fn previous_word_start(&self, x: usize) -> usize {
+        let (boundaries, classes) = self.grapheme_classes();
+        let mut i = boundaries.iter().filter(|&&boundary| boundary < x).count();
+
+        while i > 0 && classes[i - 1] == CharClass::Whitespace {
+            i -= 1;
+        }
+        if i > 0 {
+            let class = classes[i - 1];
+            while i > 0 && classes[i - 1] == class {
+                i -= 1;
+            }
+        }
+
+        boundaries[i]
+    }
+
+    // Skips over any whitespace and then the word after
+    // it, returning where that word ends
+    fn next_word_end(&self, x: usize) -> usize {
+        let (boundaries, classes) = self.grapheme_classes();
+        let mut i = boundaries.iter().filter(|&&boundary| boundary < x).count();
+
+        while i < classes.len() && classes[i] == CharClass::Whitespace {
+            i += 1;
+        }
+        if i < classes.len() {
+            let class = classes[i];
+            while i < classes.len() && classes[i] == class {
+                i += 1;
+            }
+        }
+
+        boundaries[i]
+    }
+
+    // Grapheme boundaries of the row along with the class of
+    // each cluster, which is the class of its first character
+    fn grapheme_classes(&self) -> (Vec<usize>, Vec<CharClass>) {
+        let classes = self
+            .row_content
+            .graphemes(true)
+            .map(|grapheme| CharClass::of(grapheme.chars().next().unwrap_or(' ')))
+            .collect();
+        (self.grapheme_boundaries(), classes)
+    }
+
+    // A cursor_x inside a grapheme cluster counts the whole
+    // cluster, so the cursor's end is past all of it
+    fn get_render_x(&self, cursor_x: usize, tab_stop: usize) -> usize {
+        let mut x = 0;
+        let mut render_x = 0;
+
+        for grapheme in self.row_content.graphemes(true) {
+            if x >= cursor_x {
+                break;
+            }
+            x += grapheme.chars().count();
+            render_x += if grapheme == "\t" {
+                (tab_stop - 1) - (render_x % tab_stop) + 1
+            } else {
+                grapheme_width(grapheme)
+            };
+        }
+
+        render_x
+    }
+
+    fn get_row_content_x(&self, render_x: usize, tab_stop: usize) -> usize {
+        let mut current_render_x = 0;
+        let mut cursor_x = 0;
+
+        for grapheme in self.row_content.graphemes(true) {
+            if grapheme == "\t" {
+                current_render_x += (tab_stop - 1) - (current_render_x % tab_stop) + 1;
+            } else {
+                current_render_x += grapheme_width(grapheme);
+            }
+
+            if current_render_x > render_x {
+                return cursor_x;
+            }
+            cursor_x += grapheme.chars().count();
+        }
+
+        0
+    }
+
+    // Render columns each visual row starts at when the row is
+    // soft wrapped to `width` columns. Rows break after the last
+    // space that fits, or mid-word when there is none. A row
+    // that exactly fills its last visual row gets an empty one
+    // after it, so the cursor at the end of it stays on screen
+    fn wrap_starts(&self, width: usize) -> Vec<usize> {
+        let width = cmp::max(width, 1);
+        let render: Vec<&str> = self.render.graphemes(true).collect();
+        let mut starts = vec![0];
+        let mut start = 0;
+
+        while render.len() - start >= width {
+            // A wide character that doesn't fit goes on the next row
+            let fallback = if width > 1 && render.get(start + width) == Some(&WIDE_FILLER) {
+                start + width - 1
+            } else {
+                start + width
+            };
+            let end = (start + 2..=start + width)
+                .rev()
+                .find(|&end| render[end - 1] == " ")
+                .unwrap_or(fallback);
+            starts.push(end);
+            start = end;
+        }
+
+        starts
+    }
+}
+
+// Rows are stored in a gap buffer: `before` holds the items up
+// to the gap in order and `after` the ones past it in reverse.
+// Inserting or removing at the gap is just a push or pop, and
+// moving the gap costs the distance it moves, so a run of edits
+// in one place stays cheap however large the file is
+struct GapBuffer<T> {
+    before: Vec<T>,
+    after: Vec<T>,
+}
+
+impl<T> GapBuffer<T> {
+    fn new() -> Self {
+        Self {
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.before.len() + self.after.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn move_gap(&mut self, at: usize) {
+        if at < self.before.len() {
+            let moved = self.before.drain(at..).rev();
+            self.after.extend(moved);
+        } else if at > self.before.len() {
+            let start = self.after.len() - (at - self.before.len());
+            let moved = self.after.drain(start..).rev();
+            self.before.extend(moved);
+        }
+    }
+
+    fn get(&self, at: usize) -> &T {
+        match at.checked_sub(self.before.len()) {
+            None => &self.before[at],
+            Some(index) => &self.after[self.after.len() - 1 - index],
+        }
+    }
+
+    fn get_mut(&mut self, at: usize) -> &mut T {
+        match at.checked_sub(self.before.len()) {
+            None => &mut self.before[at],
+            Some(index) => {
+                let index = self.after.len() - 1 - index;
+                &mut self.after[index]
+            }
+        }
+    }
+
+    fn insert(&mut self, at: usize, item: T) {
+        assert!(at <= self.len(), "insertion index out of bounds");
+        self.move_gap(at);
+        self.before.push(item);
+    }
+
+    fn remove(&mut self, at: usize) -> T {
+        assert!(at < self.len(), "removal index out of bounds");
+        self.move_gap(at);
+        self.after.pop().unwrap()
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        let (a, b) = (cmp::min(a, b), cmp::max(a, b));
+        if a == b {
+            return;
+        }
+        // With the gap right before b, a is in `before`
+        // and b is the last item of `after`
+        self.move_gap(b);
+        mem::swap(&mut self.before[a], self.after.last_mut().unwrap());
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.before.iter().chain(self.after.iter().rev())
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.before.iter_mut().chain(self.after.iter_mut().rev())
+    }
+}
+
+impl<T> FromIterator<T> for GapBuffer<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            before: iter.into_iter().collect(),
+            after: Vec::new(),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Splits a :line or :line:column off the end of a file
+// argument, the way compilers and grep print locations, into
+// the file and the 0 based x, y to put the cursor at. Names
+// that don't end in numbers and files that exist with the
+// whole argument as their name are left as they are
+fn parse_file_arg(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    fn split(text: &str) -> Option<(&str, usize)> {
+        let (rest, number) = text.rsplit_once(':')?;
+        Some((rest, number.parse().ok().filter(|&number| number > 0)?))
+    }
+
+    if Path::new(arg).exists() {
+        return (arg.into(), None);
+    }
+    match split(arg) {
+        None => (arg.into(), None),
+        Some((rest, last)) => match split(rest) {
+            Some((file, line)) => (file.into(), Some((last - 1, line - 1))),
+            None => (rest.into(), Some((0, last - 1))),
+        },
+    }
+}
+
+// Counts of the text in a buffer or a selection. Line breaks
+// count as the characters they're written as
+#[derive(Default)]
+struct TextStats {
+    lines: usize,
+    words: usize,
+    chars: usize,
+    bytes: usize,
+}
+
+impl TextStats {
+    fn count<'a>(lines: impl Iterator<Item = &'a str>, line_ending: &str) -> Self {
+        let mut stats = Self::default();
+        for line in lines {
+            if stats.lines > 0 {
+                stats.chars += line_ending.chars().count();
+                stats.bytes += line_ending.len();
+            }
+            stats.lines += 1;
+            stats.words += line.split_whitespace().count();
+            stats.chars += line.chars().count();
+            stats.bytes += line.len();
+        }
+        stats
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} lines, {} words, {} chars, {} bytes",
+            self.lines, self.words, self.chars, self.bytes
+        )
+    }
+}
+
+// Used to store contents of rows in the
+pub struct EditorRows {
+    row_contents: GapBuffer<Row>,
+    filename: Option<PathBuf>,
+    tab_stop: usize,
+    line_ending: LineEnding,
+    syntax: &'static dyn SyntaxHighlight,
+    // When the file was last changed as of loading or saving
+    // it, to notice other programs changing it
+    modified: Option<SystemTime>,
+}
+
+// A buffer opened at startup, with where to put the cursor in
+// it and the error reading its file if there was one
+type OpenedFile = (EditorRows, Option<(usize, usize)>, Option<String>);
+
+impl EditorRows {
+    // Opens every file passed as an argument other than the
+    // -R and --replay flags, each with the position given after
+    // its name if any and the error reading it if it couldn't
+    // be read. Such a file gets an empty buffer with its name.
+    // Without any arguments, text piped into stdin is opened as
+    // an unnamed buffer
+    fn new(tab_stop: usize) -> Vec<OpenedFile> {
+        let mut args = env::args().skip(1);
+        let mut files = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-R" => {}
+                // The file after --replay holds keys, not text
+                "--replay" => {
+                    args.next();
+                }
+                _ => files.push(parse_file_arg(&arg)),
+            }
+        }
+        if files.is_empty() {
+            return vec![if io::stdin().is_terminal() {
+                (Self::empty(tab_stop), None, None)
+            } else {
+                Self::from_stdin(tab_stop)
+            }];
+        }
+
+        files
+            .into_iter()
+            .map(|(file, position)| match Self::from_file(file.clone(), tab_stop) {
+                Ok(editor_rows) => (editor_rows, position, None),
+                Err(err) => {
+                    let error = format!("Could not open {}: {}", file.display(), err);
+                    let mut editor_rows = Self::empty(tab_stop);
+                    editor_rows.set_filename(file);
+                    (editor_rows, None, Some(error))
+                }
+            })
+            .collect()
+    }
+
+    fn from_stdin(tab_stop: usize) -> OpenedFile {
+        let mut contents = String::new();
+        match io::stdin().read_to_string(&mut contents) {
+            Ok(_) => (Self::from_contents(None, &contents, tab_stop), None, None),
+            Err(err) => (
+                Self::empty(tab_stop),
+                None,
+                Some(format!("Could not read stdin: {}", err)),
+            ),
+        }
+    }
+
+    pub fn empty(tab_stop: usize) -> Self {
+        Self {
+            row_contents: GapBuffer::new(),
+            filename: None,
+            tab_stop,
+            line_ending: LineEnding::Lf,
+            syntax: &PLAIN_HIGHLIGHT,
+            modified: None,
+        }
+    }
+
+    // Naming the buffer can change its file type, so every
+    // row is highlighted again
+    pub fn set_filename(&mut self, filename: PathBuf) {
+        self.rename(Some(filename), None);
+    }
+
+    // Also used to give the buffer back the name and modified
+    // time it had before set_filename
+    fn rename(&mut self, filename: Option<PathBuf>, modified: Option<SystemTime>) {
+        self.syntax = syntax_for(filename.as_deref());
+        self.filename = filename;
+        self.modified = modified;
+        self.row_contents.iter_mut().for_each(|row| {
+            row.highlight = None;
+            row.drawn = None;
+        });
+    }
+
+    fn update_highlight(&mut self, at: usize) {
+        let syntax = self.syntax;
+        let row = self.get_editor_row_mut(at);
+        if row.highlight.is_none() {
+            // Highlighting goes by characters, each column takes
+            // the kind of the first character drawn in it
+            let highlight = syntax.highlight(&row.render);
+            let mut index = 0;
+            row.highlight = Some(
+                row.render
+                    .graphemes(true)
+                    .map(|grapheme| {
+                        let kind = highlight[index];
+                        index += grapheme.chars().count();
+                        kind
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    fn set_tab_stop(&mut self, tab_stop: usize) {
+        self.tab_stop = tab_stop;
+        self.row_contents
+            .iter_mut()
+            .for_each(|row| Self::render_row(row, tab_stop));
+    }
+
+    fn override_line_ending(&mut self, config: &Config) {
+        if let Some(line_ending) = config.line_ending {
+            self.line_ending = line_ending;
+        }
+    }
+
+    // Drops every row's cached drawing, for when something the
+    // cache isn't keyed on (like the colors) changes
+    fn forget_drawn(&mut self) {
+        self.row_contents.iter_mut().for_each(|row| row.drawn = None);
+    }
+
+    fn render_row(row: &mut Row, tab_stop: usize) {
+        let mut index = 0;
+
+        let capacity = row
+            .row_content
+            .chars()
+            .fold(0, |acc, next| acc + if next == '\t' { tab_stop } else { 1 });
+
+        row.render = String::with_capacity(capacity);
+        row.highlight = None;
+        row.drawn = None;
+        row.misspelled = None;
+        row.tabs.clear();
+        row.row_content.graphemes(true).for_each(|grapheme| {
+            index += 1;
+            if grapheme == "\t" {
+                let start = index - 1;
+                row.render.push(' ');
+                while index % tab_stop != 0 {
+                    row.render.push(' ');
+                    index += 1;
+                }
+                row.tabs.push((start, index));
+            } else if grapheme == "\0" {
+                // A NUL in the file would be taken for the filler
+                // after a wide character, so it's drawn as a symbol
+                row.render.push('\u{2400}');
+            } else {
+                row.render.push_str(grapheme);
+                if grapheme_width(grapheme) == 2 {
+                    row.render.push_str(WIDE_FILLER);
+                    index += 1;
+                }
+            }
+        });
+    }
+
+    pub fn insert_row(&mut self, at: usize, contents: String) {
+        // self.row_contents.push(Row::default());
+        let mut new_row = Row::new(contents, String::new());
+        EditorRows::render_row(&mut new_row, self.tab_stop);
+        self.row_contents.insert(at, new_row);
+    }
+
+    fn split_row(&mut self, at: usize, x: usize) {
+        if x == 0 {
+            self.insert_row(at, String::new());
+            return;
+        }
+
+        let tab_stop = self.tab_stop;
+        let current_row = self.get_editor_row_mut(at);
+        let split_at = current_row.byte_index(x);
+        let new_row_content: String = current_row.row_content[split_at..].into();
+        current_row.row_content.truncate(split_at);
+
+        Self::render_row(current_row, tab_stop);
+        self.insert_row(at + 1, new_row_content);
+    }
+
+    fn insert_str(&mut self, at: usize, x: usize, string: &str) {
+        let tab_stop = self.tab_stop;
+        self.get_editor_row_mut(at).insert_str(x, string, tab_stop);
+    }
+
+    fn delete_str(&mut self, at: usize, x: usize, len: usize) {
+        let tab_stop = self.tab_stop;
+        self.get_editor_row_mut(at).delete_str(x, len, tab_stop);
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        self.row_contents.swap(a, b);
+    }
+
+    fn remove_row(&mut self, at: usize) {
+        self.row_contents.remove(at);
+    }
+
+    fn join_adjacent_rows(&mut self, at: usize) {
+        let tab_stop = self.tab_stop;
+        let current_row = self.row_contents.remove(at);
+        let previous_row = self.get_editor_row_mut(at - 1);
+
+        previous_row.row_content.push_str(&current_row.row_content);
+        Self::render_row(previous_row, tab_stop);
+    }
+
+    fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
+        self.row_contents.get_mut(at)
+    }
+
+    pub fn from_file(file: PathBuf, tab_stop: usize) -> io::Result<Self> {
+        // Taken before reading so a change made while the file
+        // is being read still counts as newer
+        let modified = fs::metadata(&file).and_then(|file| file.modified()).ok();
+        let file_contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            // A file that doesn't exist yet opens as an empty
+            // buffer with its name set, saving then creates it
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut editor_rows = Self::from_contents(Some(file), &file_contents, tab_stop);
+        editor_rows.modified = modified;
+        Ok(editor_rows)
+    }
+
+    pub fn from_contents(filename: Option<PathBuf>, contents: &str, tab_stop: usize) -> Self {
+        Self {
+            syntax: syntax_for(filename.as_deref()),
+            filename,
+            row_contents: contents
+                .lines()
+                .map(|it| {
+                    let mut row = Row::new(it.into(), String::new());
+                    Self::render_row(&mut row, tab_stop);
+                    row
+                })
+                .collect(),
+            tab_stop,
+            line_ending: LineEnding::detect(contents),
+            modified: None,
+        }
+    }
+
+    fn display_name(&self) -> &str {
+        self.filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("[No Name]")
+    }
+
+    pub fn get_editor_row(&self, at: usize) -> &Row {
+        self.row_contents.get(at)
+    }
+
+    pub fn number_of_rows(&self) -> usize {
+        self.row_contents.len()
+    }
+
+    // The buffer as a standalone HTML page, colored the way it's
+    // highlighted on screen. Rows are taken from their render so
+    // tabs come out as the spaces they're drawn as
+    fn to_html(&self, theme: &Theme) -> String {
+        let css_color = |kind: HighlightKind| {
+            ColorDepth::rgb(kind.color(theme))
+                .map(|(r, g, b)| format!("#{:02x}{:02x}{:02x}", r, g, b))
+        };
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             </head>\n<body>\n<pre style=\"font-family: monospace\">",
+            escape_html(self.display_name())
+        );
+        for (index, row) in self.row_contents.iter().enumerate() {
+            if index > 0 {
+                html.push('\n');
+            }
+            let highlight = self.syntax.highlight(&row.render);
+            let mut current = HighlightKind::Normal;
+            for (c, kind) in row.render.chars().zip(highlight) {
+                if c == '\0' {
+                    continue;
+                }
+                if kind != current {
+                    if css_color(current).is_some() {
+                        html.push_str("</span>");
+                    }
+                    if let Some(color) = css_color(kind) {
+                        html.push_str(&format!("<span style=\"color: {}\">", color));
+                    }
+                    current = kind;
+                }
+                html.push_str(&escape_html(&c.to_string()));
+            }
+            if css_color(current).is_some() {
+                html.push_str("</span>");
+            }
+        }
+        html.push_str("</pre>\n</body>\n</html>\n");
+        html
+    }
+
+    fn stats(&self) -> TextStats {
+        TextStats::count(
+            self.row_contents.iter().map(|row| row.row_content.as_str()),
+            self.line_ending.as_str(),
+        )
+    }
+
+    // Rows are split with lines() when loading, which already
+    // drops the final newline and the \r of a \r\n, so writing
+    // them back here makes a load and save round trip byte
+    // identical
+    pub fn save(&mut self, trailing_newline: bool) -> io::Result<usize> {
+        let len = match &self.filename {
+            None => return Err(io::Error::other("no file name specified")),
+            Some(name) => self.replace_file(name, trailing_newline)?,
+        };
+        self.modified = self.disk_modified();
+        Ok(len)
+    }
+
+    // Writes the rows to a new file next to the one at path and
+    // renames it over that, so a save that fails halfway leaves
+    // the old file as it was. When the directory can't hold the
+    // new file or the rename fails the file is written in place
+    fn replace_file(&self, path: &Path, trailing_newline: bool) -> io::Result<usize> {
+        // A symlink is followed so the file it points to is
+        // replaced and not the link itself
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let temp = match target.file_name() {
+            Some(name) => target.with_file_name(format!(
+                ".{}.pound-save-{}",
+                name.to_string_lossy(),
+                std::process::id()
+            )),
+            None => return self.write_to(&target, trailing_newline),
+        };
+        let file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+        {
+            Ok(file) => file,
+            Err(_) => return self.write_to(&target, trailing_newline),
+        };
+
+        let written = self.write_rows(&file, trailing_newline).and_then(|written| {
+            // The new file takes over the permissions of the old
+            // one, so a script stays executable
+            if let Ok(metadata) = fs::metadata(&target) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            file.sync_all()?;
+            Ok(written)
+        });
+        drop(file);
+        match written.map(|written| (written, fs::rename(&temp, &target))) {
+            Ok((written, Ok(()))) => Ok(written),
+            Ok((_, Err(_))) => {
+                let _ = fs::remove_file(&temp);
+                self.write_to(&target, trailing_newline)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                Err(err)
+            }
+        }
+    }
+
+    fn disk_modified(&self) -> Option<SystemTime> {
+        let filename = self.filename.as_ref()?;
+        fs::metadata(filename).and_then(|file| file.modified()).ok()
+    }
+
+    // The time the file was changed at if that happened after
+    // it was loaded or saved here
+    fn changed_on_disk(&self) -> Option<SystemTime> {
+        self.disk_modified()
+            .filter(|&modified| self.modified.is_none_or(|known| modified > known))
+    }
+
+    fn write_to(&self, path: &Path, trailing_newline: bool) -> io::Result<usize> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let written = self.write_rows(&file, trailing_newline)?;
+        // The file is only cut to its new length once all of it
+        // was written, opening it with truncate would lose the old
+        // contents when the write fails
+        file.set_len(written as u64)?;
+        Ok(written)
+    }
+
+    // Rows are written out one at a time rather than
+    // joined into a copy of the whole file first
+    fn write_rows(&self, file: &fs::File, trailing_newline: bool) -> io::Result<usize> {
+        let mut writer = io::BufWriter::new(file);
+        let line_ending = self.line_ending.as_str().as_bytes();
+        let mut written = 0;
+        for (index, row) in self.row_contents.iter().enumerate() {
+            if index > 0 {
+                writer.write_all(line_ending)?;
+                written += line_ending.len();
+            }
+            writer.write_all(row.row_content.as_bytes())?;
+            written += row.row_content.len();
+        }
+        if trailing_newline && !self.row_contents.is_empty() {
+            writer.write_all(line_ending)?;
+            written += line_ending.len();
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    // The recovery file sits next to the file as a hidden
+    // .name.pound.swp, a buffer without a name gets one in
+    // the temporary directory for this process
+    fn swap_path(&self) -> PathBuf {
+        match self
+            .filename
+            .as_ref()
+            .and_then(|path| Some((path, path.file_name()?)))
+        {
+            Some((path, name)) => {
+                path.with_file_name(format!(".{}.pound.swp", name.to_string_lossy()))
+            }
+            None => env::temp_dir().join(format!("pound-{}.swp", std::process::id())),
+        }
+    }
+}
+
+// The actual text editor struct, includes the key
+// press reader and also the output that will be displayed
+// in this text editor.
+pub struct Editor {
+    reader: Reader,
+    // The buffer being edited, the other open buffers are
+    // kept in buffers and current is the position of this
+    // one in the whole list
+    output: Output,
+    buffers: Vec<Output>,
+    current: usize,
+    // Index of the buffer shown next to the current one
+    // when the screen is split
+    split: Option<usize>,
+    quit_times: u8,
+    clipboard: Option<Clipboard>,
+    // Searched for keywords of every buffer, oldest first
+    search_history: Vec<String>,
+    // Where the left mouse button went down while it's held
+    drag_anchor: Option<(usize, usize)>,
+    // An operator typed in Normal mode waiting for the rest
+    // of the command, like the first d of dd
+    pending_operator: Option<char>,
+    // The count typed before a Normal mode command, like
+    // the 5 of 5j
+    pending_count: Option<usize>,
+    // When the screen was last drawn, see MAX_FRAME_DELAY
+    drawn_at: Instant,
+}
+
+// While keys are still waiting to be read the screen is only
+// drawn once they're all handled, so holding a key down draws
+// one frame for a burst of repeats. Keys coming in faster than
+// they're handled still get a frame this often
+const MAX_FRAME_DELAY: Duration = Duration::from_millis(50);
+
+// Counts are capped so a long run of digits can't have a
+// command repeat for ages
+const MAX_COUNT: usize = 99_999;
+
+const QUIT_TIMES: u8 = 2;
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        let terminal_size = terminal::size().unwrap();
+        let (config, config_error) = Config::load();
+        let read_only = env::args().skip(1).any(|arg| arg == "-R");
+        let (reader, replay_error) = match env::args().skip_while(|arg| arg != "--replay").nth(1) {
+            Some(path) => match ReplayedKeys::load(&path) {
+                Ok(keys) => (Reader::with_keys(Box::new(keys)), None),
+                Err(err) => (Reader::new(), Some(err)),
+            },
+            None => (Reader::new(), None),
+        };
+        let files = EditorRows::new(config.tab_stop);
+        let first_error = files.iter().find_map(|(_, _, error)| error.clone());
+        let help = first_error.or(replay_error).or(config_error).unwrap_or_else(|| {
+            "Help: CTRL + S to Save | CTRL + F to Find | CTRL + Z to Undo | CTRL + Q to Quit."
+                .into()
+        });
+
+        // The first file is shown, the rest wait in the other
+        // buffers. A file that couldn't be read is opened read-only
+        // so saving doesn't replace it with an empty one
+        let mut outputs = files.into_iter().map(|(mut editor_rows, position, error)| {
+            editor_rows.override_line_ending(&config);
+            let unreadable = error.is_some() && editor_rows.filename.is_some();
+            let message = error.unwrap_or_else(|| help.clone());
+            let mut output = Output::new(editor_rows, config.clone(), message, terminal_size);
+            output.read_only = read_only || unreadable;
+            if let Some(position) = position {
+                output.place_cursor(position);
+            }
+            output
+        });
+        let output = outputs.next().unwrap();
+        Self::with_buffers(reader, output, outputs.collect())
+    }
+
+    // An editor for a single buffer that reads its events from
+    // keys instead of the terminal and draws nowhere, to drive
+    // it from tests. With ScriptedKeys, run returns an
+    // UnexpectedEof error once the events run out
+    pub fn scripted(
+        editor_rows: EditorRows,
+        config: Config,
+        terminal_size: (u16, u16),
+        keys: impl KeyProvider + 'static,
+    ) -> Self {
+        let mut output = Output::new(editor_rows, config, String::new(), terminal_size);
+        output.editor_contents.headless = true;
+        Self::with_buffers(Reader::with_keys(Box::new(keys)), output, Vec::new())
+    }
+
+    fn with_buffers(reader: Reader, output: Output, buffers: Vec<Output>) -> Self {
+        Self {
+            reader,
+            output,
+            buffers,
+            current: 0,
+            split: None,
+            quit_times: QUIT_TIMES,
+            clipboard: None,
+            search_history: Vec::new(),
+            drag_anchor: None,
+            pending_operator: None,
+            pending_count: None,
+            drawn_at: Instant::now(),
+        }
+    }
+
+    // A recovery file newer than the file it's for is left from
+    // a session that didn't exit cleanly, so the user is asked
+    // whether to load it in place of the file
+    pub fn recover(&mut self) -> io::Result<()> {
+        let editor_rows = &self.output.editor_rows;
+        let filename = match &editor_rows.filename {
+            None => return Ok(()),
+            Some(_) if self.output.read_only => return Ok(()),
+            Some(filename) => filename.clone(),
+        };
+        let swap_path = editor_rows.swap_path();
+        let swap_modified = match fs::metadata(&swap_path).and_then(|file| file.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return Ok(()),
+        };
+        if fs::metadata(&filename)
+            .and_then(|file| file.modified())
+            .is_ok_and(|modified| modified >= swap_modified)
+        {
+            return Ok(());
+        }
+
+        let answer = prompt!(
+            &mut self.output,
+            &mut self.reader,
+            "Found unsaved changes to this file, recover them? (y/N) {}"
+        );
+        if !answer.is_some_and(|answer| answer.eq_ignore_ascii_case("y")) {
+            return Ok(());
+        }
+
+        match fs::read_to_string(&swap_path) {
+            Ok(contents) => {
+                let tab_stop = self.output.config.tab_stop;
+                let mut editor_rows =
+                    EditorRows::from_contents(Some(filename), &contents, tab_stop);
+                editor_rows.override_line_ending(&self.output.config);
+                editor_rows.modified = editor_rows.disk_modified();
+                self.output.editor_rows = editor_rows;
+                // The recovered text isn't in the file yet, and
+                // undoing edits made to it doesn't put it there
+                self.output.dirty = 1;
+                self.output.edit_history.mark_unsaved();
+                self.output.swap = Some((swap_path, 1));
+                self.output
+                    .status_message
+                    .set_message("Recovered unsaved changes".into());
+            }
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Could not read recovery file: {}", err)),
+        }
+        Ok(())
+    }
+
+    // The buffer being edited
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    // Recovery files of every open buffer
+    pub fn swap_files(&self) -> Vec<PathBuf> {
+        iter::once(&self.output)
+            .chain(&self.buffers)
+            .filter_map(|output| output.swap.as_ref().map(|(path, _)| path.clone()))
+            .collect()
+    }
+
+    fn terminal_size(&self) -> (u16, u16) {
+        (
+            self.output.terminal_size.0 as u16,
+            self.output.terminal_size.1 as u16,
+        )
+    }
+
+    // Position in buffers of the buffer at index in the
+    // whole list, which can't be the current one
+    fn buffer_index(&self, index: usize) -> usize {
+        if index < self.current {
+            index
+        } else {
+            index - 1
+        }
+    }
+
+    // Makes the buffer at index (in the whole list) the
+    // current one. Only the current buffer sees resizes,
+    // so it's given the current size
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current {
+            return;
+        }
+
+        let terminal_size = self.terminal_size();
+        let output = self.buffers.remove(self.buffer_index(index));
+        let previous = mem::replace(&mut self.output, output);
+        let previous_position = if index < self.current {
+            self.current - 1
+        } else {
+            self.current
+        };
+        self.buffers.insert(previous_position, previous);
+
+        self.current = index;
+        self.quit_times = QUIT_TIMES;
+        self.output.resize(terminal_size.0, terminal_size.1);
+    }
+
+    // Shows the next or previous buffer in the current pane,
+    // skipping the one shown in the other pane of a split
+    fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len() + 1;
+        let shown = if self.split.is_some() { 2 } else { 1 };
+        if count == shown {
+            self.output
+                .status_message
+                .set_message("No other buffers open".into());
+            return;
+        }
+
+        let step = |index: usize| {
+            if forward {
+                (index + 1) % count
+            } else {
+                (index + count - 1) % count
+            }
+        };
+        let mut index = step(self.current);
+        if self.split == Some(index) {
+            index = step(index);
+        }
+
+        // The pane is set before switching so the buffer is
+        // resized to fit it
+        let position = self.buffer_index(index);
+        self.buffers[position].pane = self.output.pane;
+        self.switch_buffer(index);
+    }
+
+    // Splits the screen into the current buffer on the left
+    // and the next one on the right, or goes back to showing
+    // only the current buffer
+    fn toggle_split(&mut self) {
+        // Hidden buffers get resized to their pane when
+        // they're switched to, so only the pane is set
+        if let Some(other) = self.split.take() {
+            let position = self.buffer_index(other);
+            self.buffers[position].pane = Pane::Full;
+            self.output.set_pane(Pane::Full);
+            return;
+        }
+
+        if self.buffers.is_empty() {
+            self.output
+                .status_message
+                .set_message("Open another file to split the screen".into());
+            return;
+        }
+
+        let other = (self.current + 1) % (self.buffers.len() + 1);
+        let terminal_size = self.terminal_size();
+        let position = self.buffer_index(other);
+        // The other buffer may not have seen the latest resize
+        self.buffers[position].pane = Pane::Right;
+        self.buffers[position].resize(terminal_size.0, terminal_size.1);
+        self.output.set_pane(Pane::Left);
+        self.split = Some(other);
+    }
+
+    // Moves the focus to the left or right pane of a split
+    fn focus_pane(&mut self, pane: Pane) {
+        if let Some(other) = self.split {
+            if self.output.pane != pane {
+                let current = self.current;
+                self.switch_buffer(other);
+                self.split = Some(current);
+            }
+        }
+    }
+
+    // A left click in the text area of either pane moves the
+    // cursor there, focusing the pane if it's the other one.
+    // The wheel scrolls the current pane
+    fn mouse(&mut self, mouse_event: MouseEvent) {
+        let (column, row) = (mouse_event.column as usize, mouse_event.row as usize);
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {}
+            MouseEventKind::Drag(MouseButton::Left) => return self.drag(column, row),
+            MouseEventKind::Up(MouseButton::Left) => {
+                // A click without any movement leaves nothing selected
+                self.drag_anchor = None;
+                if let Some(selection) = self.output.selection {
+                    if selection.anchor == selection.active {
+                        self.output.selection = None;
+                    }
+                }
+                return;
+            }
+            kind @ (MouseEventKind::ScrollUp | MouseEventKind::ScrollDown) => {
+                let output = &mut self.output;
+                output.cursor_controller.scroll_view(
+                    kind == MouseEventKind::ScrollUp,
+                    output.config.scroll_lines,
+                    &output.editor_rows,
+                    output.config.scrolloff,
+                );
+                return;
+            }
+            _ => return,
+        }
+
+        let output = &self.output;
+        if column < output.x_offset || column >= output.x_offset + output.win_size.0 {
+            match output.pane {
+                Pane::Left => self.focus_pane(Pane::Right),
+                Pane::Right => self.focus_pane(Pane::Left),
+                Pane::Full => {}
+            }
+        }
+
+        let output = &mut self.output;
+        if row >= output.win_size.1 {
+            return;
+        }
+        let column = column.saturating_sub(output.x_offset + output.gutter_width());
+        output
+            .cursor_controller
+            .click(column, row, &output.editor_rows);
+        output.selection = None;
+        let cursor = &output.cursor_controller;
+        self.drag_anchor = Some((cursor.cursor_x, cursor.cursor_y));
+    }
+
+    // Moves the active end of the selection started by the last
+    // click, scrolling when the drag reaches the top or bottom
+    // row of the pane. Drags stay in the pane they started in
+    fn drag(&mut self, column: usize, row: usize) {
+        let anchor = match self.drag_anchor {
+            Some(anchor) => anchor,
+            None => return,
+        };
+        let output = &mut self.output;
+        let last_row = output.win_size.1.saturating_sub(1);
+        if row == 0 || row >= last_row {
+            output.cursor_controller.scroll_view(
+                row == 0,
+                1,
+                &output.editor_rows,
+                output.config.scrolloff,
+            );
+        }
+        let column = column.saturating_sub(output.x_offset + output.gutter_width());
+        output
+            .cursor_controller
+            .click(column, row.min(last_row), &output.editor_rows);
+        let cursor = &output.cursor_controller;
+        output.selection = Some(Selection {
+            anchor,
+            active: (cursor.cursor_x, cursor.cursor_y),
+        });
+    }
+
+    // Opens the file named in the prompt in a new buffer
+    // after the current one
+    fn open_file(&mut self) -> io::Result<()> {
+        if let Some(editor_rows) = self.output.open_file(&mut self.reader)? {
+            self.add_buffer(editor_rows);
+        }
+        Ok(())
+    }
+
+    fn add_buffer(&mut self, editor_rows: EditorRows) {
+        let message = format!("Opened {}", editor_rows.display_name());
+        let mut output = Output::new(
+            editor_rows,
+            self.output.config.clone(),
+            message,
+            self.terminal_size(),
+        );
+        output.editor_contents.headless = self.output.editor_contents.headless;
+        output.set_pane(self.output.pane);
+
+        if self.output.is_pristine() {
+            self.output = output;
+        } else {
+            let previous = mem::replace(&mut self.output, output);
+            self.buffers.insert(self.current, previous);
+            self.current += 1;
+            self.quit_times = QUIT_TIMES;
+            // The new buffer comes before the one in the other
+            // pane if that one was after the current buffer
+            if let Some(other) = self.split.as_mut().filter(|other| **other >= self.current) {
+                *other += 1;
+            }
+        }
+    }
+
+    // Alt+: opens a command line for ex style commands,
+    // returns false when one of them quits the editor
+    fn command_line(&mut self) -> io::Result<bool> {
+        let input = match prompt!(&mut self.output, &mut self.reader, ":{}") {
+            None => return Ok(true),
+            Some(input) => input,
+        };
+        let (command, argument) = match input.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (input.trim(), ""),
+        };
+        if command != "stats" {
+            self.output.selection = None;
+        }
+
+        match (command, argument) {
+            ("w", "") => self.save()?,
+            ("w", filename) => {
+                if !self.output.is_read_only() {
+                    self.save_as(filename.into());
+                }
+            }
+            ("q", "") if self.output.dirty > 0 => self.output.status_message.set_message(format!(
+                "{} has unsaved changes, q! closes it anyway",
+                self.output.editor_rows.display_name()
+            )),
+            ("q" | "q!", "") => return Ok(self.close_buffer()),
+            ("wq" | "x", "") => {
+                self.save()?;
+                if self.output.dirty == 0 {
+                    return Ok(self.close_buffer());
+                }
+            }
+            ("goto", line) => self.output.jump_to_line(line),
+            ("stats", "") => self.output.show_stats(),
+            ("export", path) => self.output.export_html(path),
+            (line, "") if line.parse::<usize>().is_ok() => self.output.jump_to_line(line),
+            ("set", setting) if !setting.is_empty() => self.output.set_option(setting),
+            ("e", filename) if !filename.is_empty() => {
+                if let Some(editor_rows) = self.output.load_file(filename) {
+                    self.add_buffer(editor_rows);
+                }
+            }
+            _ => self
+                .output
+                .status_message
+                .set_message(format!("Unknown command: {}", input.trim())),
+        }
+        Ok(true)
+    }
+
+    // Saves the current buffer, asking for a file name if it
+    // has none yet
+    fn save(&mut self) -> io::Result<()> {
+        if self.output.is_read_only() {
+            return Ok(());
+        }
+        if self.output.editor_rows.filename.is_none() {
+            let prompt = prompt!(
+                &mut self.output,
+                &mut self.reader,
+                "Save as: {} (ESC to cancel)",
+                paths = true
+            );
+
+            match prompt {
+                None => {
+                    self.output
+                        .status_message
+                        .set_message("Save aborted".into());
+                    return Ok(());
+                }
+                Some(filename) => {
+                    self.save_as(filename.into());
+                    return Ok(());
+                }
+            }
+        } else if self.output.editor_rows.changed_on_disk().is_some() {
+            let answer = prompt!(
+                &mut self.output,
+                &mut self.reader,
+                "The file was changed on disk, overwrite it? (y/N) {}"
+            );
+            if !answer.is_some_and(|answer| answer.eq_ignore_ascii_case("y")) {
+                self.output
+                    .status_message
+                    .set_message("Save aborted".into());
+                return Ok(());
+            }
+        }
+
+        self.write_buffer();
+        Ok(())
+    }
+
+    // Saves under filename, keeping the name the buffer had
+    // (or that it had none, so Ctrl+S asks again) if that fails
+    fn save_as(&mut self, filename: PathBuf) {
+        let editor_rows = &mut self.output.editor_rows;
+        let (previous, modified) = (editor_rows.filename.clone(), editor_rows.modified);
+        editor_rows.set_filename(filename);
+        if !self.write_buffer() {
+            self.output.editor_rows.rename(previous, modified);
+        }
+    }
+
+    // Formats and writes the buffer to its file, returns
+    // whether it was written
+    fn write_buffer(&mut self) -> bool {
+        // The time taken includes formatting
+        let started = Instant::now();
+        // A file the formatter fails on is still saved as it is.
+        // A failed save leaves the buffer and the file as they
+        // were, so it can be saved again under another name
+        let format_error = self.output.format().err();
+        let saved = self.output.save().map(|len| {
+            format!(
+                "{} bytes, {} lines written in {}ms",
+                len,
+                self.output.editor_rows.number_of_rows(),
+                started.elapsed().as_millis()
+            )
+        });
+        let written = saved.is_ok();
+        let message = match (saved, format_error) {
+            (Err(err), _) => format!("Save failed: {}", err),
+            (Ok(written), None) => written,
+            (Ok(written), Some(err)) => format!("{}, formatting failed: {}", written, err),
+        };
+        self.output.status_message.set_message(message);
+        written
+    }
+
+    // Closes the current buffer unless it has unsaved changes,
+    // which are warned about until key is pressed quit_times
+    // more times. Returns false like close_buffer when the
+    // editor should exit
+    fn quit_buffer(&mut self, key: &str) -> io::Result<bool> {
+        if self.output.dirty > 0 && self.output.config.confirm_quit {
+            return self.confirm_quit();
+        }
+        if self.output.dirty > 0 && self.quit_times > 0 {
+            self.output.status_message.set_message_for(
+                format!(
+                    "WARNING! {} has unsaved changes. Press {} {} more times to {}.",
+                    self.output.editor_rows.display_name(),
+                    key,
+                    self.quit_times,
+                    if self.buffers.is_empty() { "quit" } else { "close it" }
+                ),
+                Some(WARNING_TIMEOUT),
+            );
+            // decrement quit times each time the key is pressed
+            self.quit_times -= 1;
+            return Ok(true);
+        }
+
+        Ok(self.close_buffer())
+    }
+
+    // Alt+q closes every buffer, quitting the editor. Buffers
+    // with unsaved changes are gone to first and warned about
+    // one at a time like Ctrl+q does, so stopping at one of
+    // them leaves the others open
+    fn quit_all(&mut self) -> io::Result<bool> {
+        if self.split.is_some() {
+            self.toggle_split();
+        }
+        loop {
+            if self.output.dirty == 0 {
+                if let Some(position) = self.buffers.iter().position(|output| output.dirty > 0) {
+                    let index = if position < self.current { position } else { position + 1 };
+                    self.switch_buffer(index);
+                }
+            }
+            let open = self.buffers.len();
+            if !self.quit_buffer("Alt+q")? {
+                return Ok(false);
+            }
+            if self.buffers.len() == open {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Asks whether to save the modified buffer before closing
+    // it, returns false like close_buffer when the editor
+    // should exit
+    fn confirm_quit(&mut self) -> io::Result<bool> {
+        self.output.status_message.set_message_for(
+            format!(
+                "Save changes to {} before {}? (y)es (n)o (c)ancel",
+                self.output.editor_rows.display_name(),
+                if self.buffers.is_empty() { "quitting" } else { "closing it" }
+            ),
+            None,
+        );
+        self.output.refresh_screen()?;
+
+        let answer = loop {
+            if let code @ (KeyCode::Char('y' | 'n' | 'c') | KeyCode::Esc) =
+                self.output.read_key(&mut self.reader)?.code
+            {
+                break code;
+            }
+        };
+
+        match answer {
+            KeyCode::Char('y') => {
+                // A save that fails or is aborted keeps the buffer
+                // open, the save has said why
+                self.save()?;
+                if self.output.dirty > 0 {
+                    return Ok(true);
+                }
+            }
+            KeyCode::Char('n') => {}
+            _ => {
+                self.output
+                    .status_message
+                    .set_message("Quit cancelled".into());
+                return Ok(true);
+            }
+        }
+        Ok(self.close_buffer())
+    }
+
+    // Stops the editor like Ctrl+Z does other programs, Ctrl+Z
+    // itself being undo. The terminal is given back to the shell
+    // until its fg continues the editor, which then draws
+    // everything again for whatever size the terminal is by then
+    #[cfg(unix)]
+    fn suspend(&mut self) -> io::Result<()> {
+        if self.output.editor_contents.headless {
+            return Ok(());
+        }
+        leave_terminal()?;
+        // Raw mode turned off the signal Ctrl+Z would send, so it's
+        // sent from here. It returns once the editor is continued
+        // SAFETY: raise only sends a signal to this process
+        unsafe {
+            libc::raise(libc::SIGTSTP);
+        }
+        enter_terminal()?;
+        let (columns, rows) = terminal::size()?;
+        self.output.resize(columns, rows);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn suspend(&mut self) -> io::Result<()> {
+        self.output
+            .status_message
+            .set_message("Suspending isn't supported here".into());
+        Ok(())
+    }
+
+    // Closes the current buffer, returns false once the
+    // last one is closed and the editor should exit
+    fn close_buffer(&mut self) -> bool {
+        self.output.remember_position();
+        if self.buffers.is_empty() {
+            return false;
+        }
+        if self.split.is_some() {
+            self.toggle_split();
+        }
+
+        let terminal_size = self.terminal_size();
+        self.current = cmp::min(self.current, self.buffers.len() - 1);
+        let mut closed = mem::replace(&mut self.output, self.buffers.remove(self.current));
+        closed.remove_swap();
+        self.quit_times = QUIT_TIMES;
+        self.output.pane = Pane::Full;
+        self.output.resize(terminal_size.0, terminal_size.1);
+        self.output.status_message.set_message(format!(
+            "Closed {}",
+            closed.editor_rows.display_name()
+        ));
+        true
+    }
+
+    // The system clipboard is only connected to the first
+    // time it's used. It's kept around after that since on
+    // some platforms copied text is lost once it's dropped
+    fn clipboard(&mut self) -> Result<&mut Clipboard, arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(Clipboard::new()?);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+
+    // Copies the selected text, or the current line
+    // when nothing is selected
+    fn copy(&mut self) {
+        if let Some(text) = self.output.selected_text() {
+            match self.clipboard().and_then(|clipboard| clipboard.set_text(text)) {
+                Ok(()) => self
+                    .output
+                    .status_message
+                    .set_message("Copied selection to clipboard".into()),
+                Err(err) => self
+                    .output
+                    .status_message
+                    .set_message(format!("Clipboard error: {}", err)),
+            }
+            return;
+        }
+
+        let cursor_y = self.output.cursor_controller.cursor_y;
+        if cursor_y >= self.output.editor_rows.number_of_rows() {
+            self.output
+                .status_message
+                .set_message("Nothing to copy".into());
+            return;
+        }
+
+        let line = self
+            .output
+            .editor_rows
+            .get_editor_row(cursor_y)
+            .row_content
+            .clone();
+
+        match self.clipboard().and_then(|clipboard| clipboard.set_text(line)) {
+            Ok(()) => self
+                .output
+                .status_message
+                .set_message("Copied line to clipboard".into()),
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Clipboard error: {}", err)),
+        }
+    }
+
+    fn paste(&mut self) {
+        match self.clipboard().and_then(|clipboard| clipboard.get_text()) {
+            Ok(text) => self.output.paste_text(&text),
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Clipboard error: {}", err)),
+        }
+    }
+
+    // Handles the keys that work differently with modal
+    // editing on. In Normal mode the keys that would type
+    // something are commands instead, returns whether the
+    // key was used up
+    fn modal_command(&mut self, key_event: KeyEvent) -> bool {
+        let output = &mut self.output;
+        if !output.config.modal {
+            return false;
+        }
+        let code = match key_event {
+            KeyEvent {
+                code: KeyCode::Esc,
+                ..
+            } => {
+                output.mode = Mode::Normal;
+                self.pending_operator = None;
+                self.pending_count = None;
+                return true;
+            }
+            KeyEvent {
+                code,
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } if output.mode == Mode::Normal => code,
+            _ => return false,
+        };
+
+        // Digits typed before a command are how many times to
+        // do it, a 0 is only part of a count after another digit
+        if let KeyCode::Char(digit @ '0'..='9') = code {
+            if digit != '0' || self.pending_count.is_some() {
+                let count = self.pending_count.unwrap_or(0) * 10 + (digit as usize - '0' as usize);
+                self.pending_count = Some(cmp::min(count, MAX_COUNT));
+                return true;
+            }
+        }
+        let count = self.pending_count.take().unwrap_or(1);
+
+        let row_len = if output.cursor_controller.cursor_y < output.editor_rows.number_of_rows() {
+            output
+                .editor_rows
+                .get_editor_row(output.cursor_controller.cursor_y)
+                .len()
+        } else {
+            0
+        };
+        match (self.pending_operator.take(), code) {
+            (None, KeyCode::Char(motion @ ('h' | 'j' | 'k' | 'l'))) => {
+                let direction = match motion {
+                    'h' => KeyCode::Left,
+                    'j' => KeyCode::Down,
+                    'k' => KeyCode::Up,
+                    _ => KeyCode::Right,
+                };
+                (0..count).for_each(|_| output.move_cursor(direction));
+            }
+            (None, KeyCode::Char('i')) => output.mode = Mode::Insert,
+            (None, KeyCode::Char('a')) => {
+                if output.cursor_controller.cursor_x < row_len {
+                    output.move_cursor(KeyCode::Right);
+                }
+                output.mode = Mode::Insert;
+            }
+            // Deleting characters stops at the end of the row,
+            // Delete takes a whole grapheme each time
+            (None, KeyCode::Char('x')) if output.cursor_controller.cursor_x < row_len => {
+                let cursor = &output.cursor_controller;
+                let left = output
+                    .editor_rows
+                    .get_editor_row(cursor.cursor_y)
+                    .grapheme_boundaries()
+                    .into_iter()
+                    .filter(|&boundary| boundary > cursor.cursor_x)
+                    .count();
+                let count = cmp::min(count, left);
+                output.edit_times(count, |output| output.delete_key(KeyCode::Delete));
+            }
+            (None, KeyCode::Char('o')) if !output.is_read_only() => {
+                output.move_cursor(KeyCode::End);
+                output.insert_indented_newline();
+                output.mode = Mode::Insert;
+            }
+            // The count of 3dd is kept for the second d
+            (None, KeyCode::Char('d')) => {
+                self.pending_operator = Some('d');
+                self.pending_count = Some(count);
+            }
+            (Some('d'), KeyCode::Char('d')) => output.edit_times(count, Output::delete_line),
+            (None, KeyCode::Char('J')) => output.edit_times(count, Output::join_lines),
+            // Other keys that type or delete text do nothing,
+            // the rest (arrows and such) work as usual
+            (_, KeyCode::Char(_) | KeyCode::Enter | KeyCode::Tab | KeyCode::BackTab) => {}
+            (_, KeyCode::Backspace | KeyCode::Delete) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn process_keypress(&mut self) -> io::Result<bool> {
+        // Check what key is pressed by the user
+        // quit editor if Ctrl+q is pressed
+        // Ctrl, Shift etc are called Key Modifiers
+        let key_event = match self.output.read_input(&mut self.reader)? {
+            Event::Key(key_event) => self.output.config.translate_key(key_event),
+            Event::Paste(text) => {
+                self.output.selection = None;
+                self.output.paste_text(&text);
+                return Ok(true);
+            }
+            Event::Mouse(mouse_event) => {
+                self.mouse(mouse_event);
+                return Ok(true);
+            }
+            _ => return Ok(true),
+        };
+        let cursor_before = (
+            self.output.cursor_controller.cursor_x,
+            self.output.cursor_controller.cursor_y,
+        );
+
+        // Tab and Shift+Tab shift the rows of a selection that
+        // spans more than one instead of replacing it
+        let shifts_lines = matches!(
+            key_event,
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } | KeyEvent {
+                code: KeyCode::BackTab,
+                modifiers: KeyModifiers::SHIFT,
+                ..
+            }
+        ) && self
+            .output
+            .selection
+            .is_some_and(|selection| selection.anchor.1 != selection.active.1);
+
+        // The command line keeps the selection for :stats, the
+        // other commands drop it
+        let opens_command_line = matches!(
+            key_event,
+            KeyEvent {
+                code: KeyCode::Char(':'),
+                modifiers,
+                ..
+            } if modifiers.contains(KeyModifiers::ALT)
+        );
+
+        // Any key other than one that extends, copies, comments
+        // out or counts the selection drops it
+        if !shifts_lines
+            && !opens_command_line
+            && !matches!(
+                key_event,
+                KeyEvent {
+                    code: KeyCode::Up
+                        | KeyCode::Down
+                        | KeyCode::Left
+                        | KeyCode::Right
+                        | KeyCode::Home
+                        | KeyCode::End,
+                    modifiers: KeyModifiers::SHIFT,
+                    ..
+                } | KeyEvent {
+                    code: KeyCode::Char('c' | '/' | '7'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } | KeyEvent {
+                    code: KeyCode::Char('w'),
+                    modifiers: KeyModifiers::ALT,
+                    ..
+                }
+            )
+        {
+            self.output.selection = None;
+        }
+
+        if self.modal_command(key_event) {
+            return Ok(true);
+        }
+
+        match key_event {
+            KeyEvent {
+                code: KeyCode::Char('q'),
+                modifiers: event::KeyModifiers::CONTROL,
+                ..
+            } => {
+                // Ctrl+q closes the current buffer, quitting once
+                // the last one is closed
+                return self.quit_buffer("Ctrl+q");
+            }
+            KeyEvent {
+                code: KeyCode::Char('q'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => return self.quit_all(),
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End),
+                modifiers: event::KeyModifiers::NONE,
+                ..
+            } => {
+                let position = self.output.cursor_controller.position();
+                self.output.move_cursor(direction);
+                // An arrow that can't go any further rings the bell
+                if self.output.cursor_controller.position() == position
+                    && !matches!(direction, KeyCode::Home | KeyCode::End)
+                {
+                    self.output.ring_bell();
+                }
+            }
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End),
+                modifiers: KeyModifiers::SHIFT,
+                ..
+            } => self.output.extend_selection(direction),
+            KeyEvent {
+                code: direction @ (KeyCode::Up | KeyCode::Down),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.move_line(direction),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.toggle_split(),
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.reload(&mut self.reader)?,
+            KeyEvent {
+                code: KeyCode::Char('.'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.repeat_edit(),
+            KeyEvent {
+                code: KeyCode::Char('m'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.match_bracket(),
+            KeyEvent {
+                code: KeyCode::Char('w'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.show_stats(),
+            KeyEvent {
+                code: KeyCode::F(3),
+                modifiers,
+                ..
+            } if modifiers.difference(KeyModifiers::SHIFT).is_empty() => {
+                self.output.find_again(modifiers.is_empty())
+            }
+            // Alt+: comes with Shift held on most keyboards
+            KeyEvent {
+                code: KeyCode::Char(':'),
+                modifiers,
+                ..
+            } if modifiers.contains(KeyModifiers::ALT) => return self.command_line(),
+            KeyEvent {
+                code: KeyCode::F(7),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                self.reader.start_recording();
+                self.output
+                    .status_message
+                    .set_message_for("Recording macro (F8 to stop)".into(), None);
+            }
+            KeyEvent {
+                code: KeyCode::F(8),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => {
+                if let Some(len) = self.reader.stop_recording() {
+                    self.output
+                        .status_message
+                        .set_message(format!("Recorded a macro of {} keys, F9 plays it", len));
+                }
+            }
+            KeyEvent {
+                code: KeyCode::F(9),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if self.reader.register.is_empty() => self
+                .output
+                .status_message
+                .set_message("No macro recorded, F7 starts recording one".into()),
+            KeyEvent {
+                code: KeyCode::F(9),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.reader.play(),
+            KeyEvent {
+                code: KeyCode::Char('l'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => {
+                self.output.read_only = !self.output.read_only;
+                self.output.status_message.set_message(format!(
+                    "Read-only {}",
+                    if self.output.read_only { "on" } else { "off" }
+                ));
+            }
+            KeyEvent {
+                code: KeyCode::Char('s'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.suspend()?,
+            KeyEvent {
+                code: KeyCode::Char('a'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.output.add_word(),
+            KeyEvent {
+                code: KeyCode::Char('z'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => {
+                self.output.config.soft_wrap = !self.output.config.soft_wrap;
+                self.output.status_message.set_message(format!(
+                    "Soft wrap {}",
+                    if self.output.config.soft_wrap { "on" } else { "off" }
+                ));
+            }
+            KeyEvent {
+                code: KeyCode::Left,
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.focus_pane(Pane::Left),
+            KeyEvent {
+                code: KeyCode::Right,
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.focus_pane(Pane::Right),
+            KeyEvent {
+                code: direction @ (KeyCode::Left | KeyCode::Right),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self
+                .output
+                .cursor_controller
+                .move_word(direction, &self.output.editor_rows),
+            KeyEvent {
+                code: direction @ (KeyCode::Home | KeyCode::End),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self
+                .output
+                .cursor_controller
+                .move_to_file_edge(direction == KeyCode::End, &self.output.editor_rows),
+            KeyEvent {
+                code: direction @ (KeyCode::PageUp | KeyCode::PageDown),
+                modifiers: event::KeyModifiers::NONE,
+                ..
+            } => self
+                .output
+                .cursor_controller
+                .move_page(direction == KeyCode::PageDown, &self.output.editor_rows),
+            KeyEvent {
+                code: KeyCode::Char('s'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.save()?;
+            }
+            KeyEvent {
+                code: KeyCode::Char('o'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.open_file()?;
+            }
+            // Ctrl+Tab can't be told apart from Tab by many
+            // terminals, so Ctrl+PageDown/PageUp work too
+            KeyEvent {
+                code: KeyCode::Tab | KeyCode::PageDown,
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.cycle_buffer(true),
+            KeyEvent {
+                code: KeyCode::BackTab,
+                modifiers,
+                ..
+            } if modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT => {
+                self.cycle_buffer(false)
+            }
+            KeyEvent {
+                code: KeyCode::BackTab | KeyCode::PageUp,
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.cycle_buffer(false),
+            // Raw mode turns off flow control so nothing else
+            // takes Ctrl+F, Ctrl+G is kept for those used to it
+            KeyEvent {
+                code: KeyCode::Char('f' | 'g'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.output.find(&mut self.reader, &mut self.search_history)?;
+            }
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.output.replace(&mut self.reader, &mut self.search_history)?;
+            }
+            KeyEvent {
+                code: KeyCode::Char('l'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.output.goto_line(&mut self.reader)?;
+            }
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.copy(),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.paste(),
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.duplicate_line(),
+            KeyEvent {
+                code: KeyCode::Char('k'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.delete_line(),
+            KeyEvent {
+                code: KeyCode::Char('j'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.join_lines(),
+            KeyEvent {
+                code: KeyCode::Char('e'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self
+                .output
+                .cursor_controller
+                .center_view(&self.output.editor_rows),
+            KeyEvent {
+                code: KeyCode::Char('t'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self
+                .output
+                .cursor_controller
+                .scroll_cursor_to(0, &self.output.editor_rows),
+            KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                // Cycles between absolute, relative and no line numbers
+                self.output.config.line_numbers = self.output.config.line_numbers.next();
+            }
+            KeyEvent {
+                code: KeyCode::Char('z'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.undo(),
+            KeyEvent {
+                code: KeyCode::Char('y'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.redo(),
+            // Terminals usually send Ctrl+Backspace as Ctrl+H
+            KeyEvent {
+                code: KeyCode::Char('w' | 'h'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.delete_word(),
+            KeyEvent {
+                code: key @ (KeyCode::Backspace | KeyCode::Delete),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.output.delete_key(key),
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.output.insert_indented_newline(),
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if shifts_lines => self.output.shift_lines(false),
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.output.insert_tab(),
+            KeyEvent {
+                code: KeyCode::BackTab,
+                modifiers: KeyModifiers::SHIFT,
+                ..
+            } => self.output.shift_lines(true),
+            // Most terminals send Ctrl+/ the same as Ctrl+7
+            KeyEvent {
+                code: KeyCode::Char('/' | '7'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => self.output.toggle_comment(),
+            KeyEvent {
+                // Used to handle a user input to the text 'editor'
+                // Handles any other key pressed by the user
+                // That isn't already mapped above.
+                // Also prevents modifiers like Ctrl to be used to enter
+                // characters (Ex: Ctrl + X shouldn't insert X).
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => self.output.type_char(ch),
+            _ => {}
+        }
+
+        if let Some(edit) = RepeatableEdit::for_key(key_event).filter(|_| !shifts_lines) {
+            self.output.record_edit(edit, cursor_before);
+        }
+        Ok(true)
+    }
+
+    pub fn run(&mut self) -> io::Result<bool> {
+        // When editor is run, refresh the screen first
+        // then start processing key presses by user
+        if !self.reader.has_input()? || self.drawn_at.elapsed() >= MAX_FRAME_DELAY {
+            self.refresh()?;
+            self.drawn_at = Instant::now();
+        }
+        self.process_keypress()
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        let count = self.buffers.len() + 1;
+        if let Some(other) = self.split {
+            let terminal_size = self.terminal_size();
+            let position = self.buffer_index(other);
+            let pane = &mut self.buffers[position];
+            if pane.terminal_size != self.output.terminal_size {
+                pane.resize(terminal_size.0, terminal_size.1);
+            }
+            pane.buffer_position = (other + 1, count);
+            pane.draw_unfocused()?;
+        }
+        self.output.buffer_position = (self.current + 1, count);
+        self.output.check_disk_change();
+        self.output.refresh_screen()
+    }
+}
+
+// Puts the terminal in the state the editor runs in. The
+// editor draws on the alternate screen, leaving what the
+// shell showed untouched
+pub fn enter_terminal() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    // Pasted text then comes as one event instead of keys
+    // that would be auto indented and auto closed
+    execute!(
+        stdout(),
+        terminal::EnterAlternateScreen,
+        EnableBracketedPaste,
+        EnableMouseCapture
+    )
+}
+
+// Gives the terminal back the way the shell had it. The screen
+// is cleared first for terminals without an alternate screen
+pub fn leave_terminal() -> io::Result<()> {
+    let _ = execute!(stdout(), DisableBracketedPaste, DisableMouseCapture);
+    terminal::disable_raw_mode()?;
+    Output::clear_screen()?;
+    execute!(stdout(), terminal::LeaveAlternateScreen)
+}
+
+// What was last written to each line of the screen, keyed
+// by where the line starts. Every buffer draws to the same
+// terminal, so this is kept once for all of them
+static SCREEN: Mutex<BTreeMap<(u16, u16), String>> = Mutex::new(BTreeMap::new());
+
+// Used to store contents of editor for one big write
+// instead of many smaller writes
+struct EditorContents {
+    content: String,
+    // The screen lines in content, as where each one is drawn
+    // and the range of content it takes up. A line that looks
+    // the same as last time isn't written again on flush
+    lines: Vec<((u16, u16), usize, usize)>,
+    // Set once the screen was cleared, nothing drawn before
+    // is on it anymore
+    forget_screen: bool,
+    // A scripted editor has no terminal, its frames are
+    // dropped on flush
+    headless: bool,
+}
+
+impl EditorContents {
+    fn new() -> Self {
+        Self {
+            content: String::new(),
+            lines: Vec::new(),
+            forget_screen: false,
+            headless: false,
+        }
+    }
+
+    fn push(&mut self, ch: char) {
+        self.content.push(ch)
+    }
+
+    fn push_str(&mut self, string: &str) {
+        self.content.push_str(string)
+    }
+
+    // Starts a line of the screen at the given column and row,
+    // everything pushed until end_line is part of it
+    fn start_line(&mut self, x: u16, y: u16) {
+        let start = self.content.len();
+        self.lines.push(((x, y), start, start));
+    }
+
+    fn end_line(&mut self) {
+        if let Some(line) = self.lines.last_mut() {
+            line.2 = self.content.len();
+        }
+    }
+
+    fn clear_screen(&mut self) {
+        let _ = queue!(self, terminal::Clear(ClearType::All));
+        self.forget_screen = true;
+    }
+
+    // For when something else may have written to the screen,
+    // every line is drawn again on the next flush
+    fn forget_screen(&mut self) {
+        self.forget_screen = true;
+    }
+}
+
+impl io::Write for EditorContents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(s) => {
+                self.content.push_str(s);
+                Ok(s.len())
+            }
+            Err(_) => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.headless {
+            self.content.clear();
+            self.lines.clear();
+            return Ok(());
+        }
+
+        let mut screen = SCREEN.lock().unwrap_or_else(PoisonError::into_inner);
+        if mem::take(&mut self.forget_screen) {
+            screen.clear();
+        }
+
+        let mut frame = String::with_capacity(self.content.len());
+        let mut written = 0;
+        for (position, start, end) in self.lines.drain(..) {
+            frame.push_str(&self.content[written..start]);
+            let line = &self.content[start..end];
+            if screen.get(&position).is_none_or(|drawn| drawn != line) {
+                let _ = cursor::MoveTo(position.0, position.1).write_ansi(&mut frame);
+                frame.push_str(line);
+                screen.insert(position, line.to_string());
+            }
+            written = end;
+        }
+        frame.push_str(&self.content[written..]);
+        self.content.clear();
+
+        let out = write!(stdout(), "{}", frame);
+        stdout().flush()?;
+        out
+    }
+}
@@ -0,0 +1,491 @@
+// Drives the editor with scripted key presses, the way they'd
+// come from the terminal, and checks what they did to the buffer
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use pound::{Config, Editor, EditorRows, ScriptedKeys};
+use std::io;
+
+const TERMINAL_SIZE: (u16, u16) = (80, 24);
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn ctrl(ch: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL))
+}
+
+fn typed(text: &str) -> Vec<Event> {
+    text.chars().map(|ch| key(KeyCode::Char(ch))).collect()
+}
+
+// Opens text in an unnamed buffer and presses the keys, returns
+// the editor and whether the keys quit it
+fn run(text: &str, events: Vec<Event>) -> (Editor, bool) {
+    let editor_rows = EditorRows::from_contents(None, text, 8);
+    run_rows(editor_rows, "", events)
+}
+
+// Like run but for rows read from a file, with config added
+// to the settings run uses
+fn run_rows(editor_rows: EditorRows, config: &str, events: Vec<Event>) -> (Editor, bool) {
+    let (config, _) = Config::parse(&format!("remember_position = false\n{}", config)).unwrap();
+    let keys = ScriptedKeys::new(events);
+    let mut editor = Editor::scripted(editor_rows, config, TERMINAL_SIZE, keys);
+    loop {
+        match editor.run() {
+            Ok(true) => {}
+            Ok(false) => return (editor, true),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return (editor, false),
+            Err(err) => panic!("the editor failed: {}", err),
+        }
+    }
+}
+
+// A file under a directory of its own in the temp directory,
+// name tells the tests apart
+fn temp_file(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("pound-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory.join("file.txt")
+}
+
+// Writes contents to a file, opens it and saves it without
+// changing anything, returns what ended up in the file
+fn saved_unchanged(name: &str, contents: &str) -> String {
+    let path = temp_file(name);
+    std::fs::write(&path, contents).unwrap();
+    let editor_rows = EditorRows::from_file(path.clone(), 8).unwrap();
+    run_rows(editor_rows, "", vec![ctrl('s')]);
+    let saved = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    saved.unwrap()
+}
+
+fn rows(editor: &Editor) -> Vec<&str> {
+    let editor_rows = editor.output().editor_rows();
+    (0..editor_rows.number_of_rows())
+        .map(|at| editor_rows.get_editor_row(at).content())
+        .collect()
+}
+
+fn cursor(editor: &Editor) -> (usize, usize) {
+    editor.output().cursor().position()
+}
+
+fn screen_cursor(editor: &Editor) -> (usize, usize) {
+    let output = editor.output();
+    output.cursor().screen_position(output.editor_rows())
+}
+
+#[test]
+fn typing_inserts_text_at_the_cursor() {
+    let (editor, _) = run("", typed("hello"));
+    assert_eq!(rows(&editor), ["hello"]);
+    assert_eq!(cursor(&editor), (5, 0));
+    assert!(editor.output().is_dirty());
+}
+
+#[test]
+fn typing_between_multibyte_characters() {
+    let mut events = typed("é");
+    events.push(key(KeyCode::Left));
+    events.extend(typed("ab"));
+    let (editor, _) = run("", events);
+    assert_eq!(rows(&editor), ["abé"]);
+    assert_eq!(cursor(&editor), (2, 0));
+}
+
+#[test]
+fn typing_after_a_multibyte_character_in_the_file() {
+    let mut events = vec![key(KeyCode::Right), key(KeyCode::Right)];
+    events.extend(typed("x"));
+    let (editor, _) = run("héllo", events);
+    assert_eq!(rows(&editor), ["héxllo"]);
+    assert_eq!(cursor(&editor), (3, 0));
+}
+
+#[test]
+fn a_file_that_does_not_exist_is_created_on_save() {
+    let path = temp_file("new");
+    let editor_rows = EditorRows::from_file(path.clone(), 8).unwrap();
+    assert_eq!(editor_rows.number_of_rows(), 0);
+    let mut events = typed("hi");
+    events.push(ctrl('s'));
+    let (editor, _) = run_rows(editor_rows, "", events);
+    let saved = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert_eq!(saved.unwrap(), "hi\n");
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn the_cursor_stays_on_screen_moving_along_a_row_of_tabs() {
+    let text = "\t".repeat(30);
+    for count in 0..=30 {
+        let (editor, _) = run(&text, vec![key(KeyCode::Right); count]);
+        assert_eq!(cursor(&editor), (count, 0));
+        assert!(screen_cursor(&editor).0 < usize::from(TERMINAL_SIZE.0));
+    }
+}
+
+#[test]
+fn page_down_onto_an_empty_row_clamps_the_cursor() {
+    let text = format!("a long line{}end", "\n".repeat(40));
+    let (editor, _) = run(&text, vec![key(KeyCode::End), key(KeyCode::PageDown)]);
+    let (x, y) = cursor(&editor);
+    assert!(y > 0 && rows(&editor)[y].is_empty());
+    assert_eq!(x, 0);
+}
+
+#[test]
+fn a_terminal_one_row_tall_still_edits() {
+    let (config, _) = Config::parse("remember_position = false").unwrap();
+    let editor_rows = EditorRows::from_contents(None, "one\ntwo", 8);
+    let mut events = vec![key(KeyCode::Down), key(KeyCode::PageDown), key(KeyCode::End)];
+    events.extend(typed("!"));
+    let keys = ScriptedKeys::new(events);
+    let mut editor = Editor::scripted(editor_rows, config, (80, 1), keys);
+    while editor.run().is_ok_and(|running| running) {}
+    assert_eq!(rows(&editor), ["one", "two!"]);
+}
+
+#[test]
+fn ctrl_end_and_ctrl_home_go_to_the_edges_of_the_file() {
+    let ctrl_key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::CONTROL));
+    let (editor, _) = run("one\ntwo\nlast", vec![ctrl_key(KeyCode::End)]);
+    assert_eq!(cursor(&editor), (4, 2));
+    let events = vec![ctrl_key(KeyCode::End), ctrl_key(KeyCode::Home)];
+    let (editor, _) = run("one\ntwo\nlast", events);
+    assert_eq!(cursor(&editor), (0, 0));
+}
+
+#[test]
+fn wide_characters_take_two_columns() {
+    let (editor, _) = run("你好world", vec![key(KeyCode::Right), key(KeyCode::Right)]);
+    assert_eq!(cursor(&editor), (2, 0));
+    assert_eq!(screen_cursor(&editor), (4, 0));
+    let (editor, _) = run("你好world", vec![key(KeyCode::End)]);
+    assert_eq!(cursor(&editor), (7, 0));
+    assert_eq!(screen_cursor(&editor), (9, 0));
+    let mut events = vec![key(KeyCode::End)];
+    events.extend(vec![key(KeyCode::Left); 6]);
+    let (editor, _) = run("你好world", events);
+    assert_eq!(cursor(&editor), (1, 0));
+    assert_eq!(screen_cursor(&editor), (2, 0));
+}
+
+#[test]
+fn pasted_line_breaks_all_start_new_rows() {
+    let (editor, _) = run("", vec![Event::Paste("one\r\ntwo\rthree\nfour".into())]);
+    assert_eq!(rows(&editor), ["one", "two", "three", "four"]);
+    assert_eq!(cursor(&editor), (4, 3));
+}
+
+#[test]
+fn a_count_of_x_stops_at_the_end_of_the_row_of_graphemes() {
+    let editor_rows = EditorRows::from_contents(None, "ae\u{301}b\nnext", 8);
+    let (editor, _) = run_rows(editor_rows, "modal = true", typed("l9x"));
+    assert_eq!(rows(&editor), ["a", "next"]);
+}
+
+#[test]
+fn enter_splits_the_row() {
+    let events = vec![key(KeyCode::Right), key(KeyCode::Right), key(KeyCode::Enter)];
+    let (editor, _) = run("abcd", events);
+    assert_eq!(rows(&editor), ["ab", "cd"]);
+    assert_eq!(cursor(&editor), (0, 1));
+}
+
+#[test]
+fn backspace_at_the_start_of_a_row_joins_it_to_the_one_above() {
+    let events = vec![key(KeyCode::Down), key(KeyCode::Backspace)];
+    let (editor, _) = run("ab\ncd", events);
+    assert_eq!(rows(&editor), ["abcd"]);
+    assert_eq!(cursor(&editor), (2, 0));
+}
+
+#[test]
+fn delete_removes_the_character_under_the_cursor() {
+    let (editor, _) = run("abc", vec![key(KeyCode::Delete)]);
+    assert_eq!(rows(&editor), ["bc"]);
+    assert_eq!(cursor(&editor), (0, 0));
+}
+
+#[test]
+fn delete_line_removes_the_row() {
+    let (editor, _) = run("one\ntwo\nthree", vec![key(KeyCode::Down), ctrl('k')]);
+    assert_eq!(rows(&editor), ["one", "three"]);
+    assert_eq!(cursor(&editor).1, 1);
+}
+
+#[test]
+fn undo_goes_back_to_the_saved_text() {
+    let mut events = typed("xy");
+    events.push(ctrl('z'));
+    let (editor, _) = run("abc", events);
+    assert_eq!(rows(&editor), ["abc"]);
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn redo_applies_the_undone_edit_again() {
+    let mut events = typed("xy");
+    events.extend([ctrl('z'), ctrl('y')]);
+    let (editor, _) = run("abc", events);
+    assert_eq!(rows(&editor), ["xyabc"]);
+    assert!(editor.output().is_dirty());
+}
+
+#[test]
+fn auto_closed_characters_are_forgotten_after_other_edits() {
+    let mut events = typed("(");
+    events.push(ctrl('z'));
+    events.push(key(KeyCode::Right));
+    events.extend(typed(")"));
+    let editor_rows = EditorRows::from_contents(None, "a)", 8);
+    let (editor, _) = run_rows(editor_rows, "auto_close = true", events);
+    assert_eq!(rows(&editor), ["a))"]);
+    assert_eq!(cursor(&editor), (2, 0));
+}
+
+#[test]
+fn trailing_whitespace_is_trimmed_on_save() {
+    let path = temp_file("trim");
+    std::fs::write(&path, "abc   \n").unwrap();
+    let editor_rows = EditorRows::from_file(path.clone(), 8).unwrap();
+    let (editor, _) = run_rows(editor_rows, "trim_trailing_whitespace = true", vec![ctrl('s')]);
+    let saved = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert_eq!(saved.unwrap(), "abc\n");
+    assert_eq!(rows(&editor), ["abc"]);
+}
+
+#[test]
+fn a_file_ending_in_a_newline_is_saved_as_it_was() {
+    assert_eq!(saved_unchanged("newline", "one\ntwo\nthree\n"), "one\ntwo\nthree\n");
+}
+
+#[test]
+fn a_file_with_crlf_line_endings_keeps_them() {
+    assert_eq!(saved_unchanged("crlf", "one\r\ntwo\r\n"), "one\r\ntwo\r\n");
+}
+
+#[test]
+fn export_colors_the_html_with_the_theme() {
+    let path = temp_file("export").with_extension("html");
+    let alt = |ch| Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::ALT));
+    let mut events = vec![alt(':')];
+    events.extend(typed(&format!("export {}", path.display())));
+    events.push(key(KeyCode::Enter));
+    let editor_rows = EditorRows::from_contents(Some("main.rs".into()), "fn 你\0", 8);
+    run_rows(editor_rows, "[theme]\nkeyword = \"#ff8000\"", events);
+    let html = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    let html = html.unwrap();
+    assert!(html.contains("<span style=\"color: #ff8000\">fn</span> 你\u{2400}</pre>"));
+}
+
+#[test]
+fn toggle_comment_comments_and_uncomments_the_selected_rows() {
+    let shift = |code| Event::Key(KeyEvent::new(code, KeyModifiers::SHIFT));
+    let text = "fn a() {}\nfn b() {}\nfn c() {}";
+    let mut events = vec![shift(KeyCode::Down), shift(KeyCode::Down), shift(KeyCode::End)];
+    events.push(ctrl('/'));
+    let editor_rows = EditorRows::from_contents(Some("main.rs".into()), text, 8);
+    let (editor, _) = run_rows(editor_rows, "", events.clone());
+    assert_eq!(rows(&editor), ["// fn a() {}", "// fn b() {}", "// fn c() {}"]);
+    events.push(ctrl('/'));
+    let editor_rows = EditorRows::from_contents(Some("main.rs".into()), text, 8);
+    let (editor, _) = run_rows(editor_rows, "", events);
+    assert_eq!(rows(&editor), ["fn a() {}", "fn b() {}", "fn c() {}"]);
+}
+
+#[test]
+fn find_moves_the_cursor_to_the_match() {
+    let mut events = vec![ctrl('f')];
+    events.extend(typed("thr"));
+    events.push(key(KeyCode::Enter));
+    let (editor, _) = run("one\ntwo\nthree", events);
+    assert_eq!(cursor(&editor), (0, 2));
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn find_next_goes_to_the_following_match() {
+    let mut events = vec![ctrl('f')];
+    events.extend(typed("foo"));
+    events.extend([key(KeyCode::Enter), key(KeyCode::F(3))]);
+    let (editor, _) = run("a foo\nbar\nfoo", events);
+    assert_eq!(cursor(&editor), (0, 2));
+}
+
+#[test]
+fn replacing_every_match_is_undone_at_once() {
+    let mut events = vec![ctrl('r')];
+    events.extend(typed("a"));
+    events.push(key(KeyCode::Enter));
+    events.extend(typed("b"));
+    events.push(key(KeyCode::Enter));
+    events.extend(typed("a"));
+    let (editor, _) = run("a a a", events.clone());
+    assert_eq!(rows(&editor), ["b b b"]);
+    events.push(ctrl('z'));
+    let (editor, _) = run("a a a", events);
+    assert_eq!(rows(&editor), ["a a a"]);
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn tab_is_typed_into_prompts_other_than_find() {
+    let mut events = vec![ctrl('r')];
+    events.extend(typed(" "));
+    events.push(key(KeyCode::Enter));
+    events.push(key(KeyCode::Tab));
+    events.push(key(KeyCode::Enter));
+    events.extend(typed("a"));
+    let (editor, _) = run("one two", events);
+    assert_eq!(rows(&editor), ["one\ttwo"]);
+}
+
+#[test]
+fn quitting_a_modified_buffer_can_be_cancelled() {
+    let mut events = typed("x");
+    events.extend([ctrl('q'), key(KeyCode::Char('c'))]);
+    let (editor, quit) = run("", events);
+    assert!(!quit);
+    assert_eq!(rows(&editor), ["x"]);
+}
+
+#[test]
+fn quitting_a_modified_buffer_without_saving() {
+    let mut events = typed("x");
+    events.extend([ctrl('q'), key(KeyCode::Char('n'))]);
+    let (_, quit) = run("", events);
+    assert!(quit);
+}
+
+#[test]
+fn quit_all_goes_to_the_modified_buffer_before_quitting() {
+    let path = temp_file("quit-all");
+    std::fs::write(&path, "other\n").unwrap();
+    let mut events = typed("x");
+    events.push(ctrl('o'));
+    events.extend(typed(&path.display().to_string()));
+    events.push(key(KeyCode::Enter));
+    events.push(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::ALT)));
+    let (editor, quit) = run("one", [events.clone(), typed("c")].concat());
+    assert!(!quit);
+    assert_eq!(rows(&editor), ["xone"]);
+    let (_, quit) = run("one", [events, typed("n")].concat());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert!(quit);
+}
+
+#[test]
+fn stats_from_the_command_line_count_the_selection() {
+    let mut events = vec![Event::Key(KeyEvent::new(KeyCode::End, KeyModifiers::SHIFT))];
+    events.push(Event::Key(KeyEvent::new(KeyCode::Char(':'), KeyModifiers::ALT)));
+    events.extend(typed("stats"));
+    events.push(key(KeyCode::Enter));
+    let (editor, _) = run("one two\nthree", events);
+    let message = editor.output().status_message().unwrap();
+    assert_eq!(
+        message,
+        "Selection: 1 lines, 2 words, 7 chars, 7 bytes | File: 2 lines, 3 words, 13 chars, 13 bytes"
+    );
+}
+
+#[test]
+fn a_played_macro_runs_to_the_end_before_the_next_key() {
+    let mut events = vec![key(KeyCode::F(7))];
+    events.extend(typed("ab"));
+    events.extend([key(KeyCode::F(8)), key(KeyCode::F(9))]);
+    events.extend(typed("c"));
+    let (editor, _) = run("", events);
+    assert_eq!(rows(&editor), ["ababc"]);
+}
+
+#[test]
+fn a_failed_save_as_asks_for_the_name_again() {
+    let path = temp_file("save-as");
+    let mut events = typed("x");
+    events.push(ctrl('s'));
+    events.extend(typed(&format!("{}/missing/file.txt", path.display())));
+    events.push(key(KeyCode::Enter));
+    events.push(ctrl('s'));
+    events.extend(typed(&path.display().to_string()));
+    events.push(key(KeyCode::Enter));
+    let (editor, _) = run("", events);
+    let saved = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert_eq!(saved.unwrap(), "x\n");
+    assert_eq!(rows(&editor), ["x"]);
+}
+
+#[test]
+fn tab_completes_the_path_to_save_as() {
+    let directory = std::env::temp_dir().join(format!("pound-complete-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("subdirectory")).unwrap();
+    let mut events = typed("x");
+    events.push(ctrl('s'));
+    events.extend(typed(&format!("{}/sub", directory.display())));
+    events.push(key(KeyCode::Tab));
+    events.extend(typed("saved.txt"));
+    events.push(key(KeyCode::Enter));
+    let (editor, _) = run("", events);
+    let saved = std::fs::read_to_string(directory.join("subdirectory/saved.txt"));
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(saved.unwrap(), "x\n");
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn join_lines_drops_the_indentation_for_one_space() {
+    let (editor, _) = run("fn main() {\n    body\n}", vec![ctrl('j')]);
+    assert_eq!(rows(&editor), ["fn main() { body", "}"]);
+    assert_eq!(cursor(&editor), (11, 0));
+}
+
+#[test]
+fn join_lines_is_undone_as_one_edit() {
+    let (editor, _) = run("one\n  two", vec![ctrl('j'), ctrl('z')]);
+    assert_eq!(rows(&editor), ["one", "  two"]);
+    assert!(!editor.output().is_dirty());
+}
+
+#[test]
+fn home_goes_to_the_first_non_blank_then_the_start_of_the_row() {
+    let mut events = vec![key(KeyCode::End), key(KeyCode::Home)];
+    let (editor, _) = run("    text", events.clone());
+    assert_eq!(cursor(&editor), (4, 0));
+    events.push(key(KeyCode::Home));
+    let (editor, _) = run("    text", events);
+    assert_eq!(cursor(&editor), (0, 0));
+}
+
+#[test]
+fn recovered_changes_stay_modified_after_undoing_the_edits_made_to_them() {
+    let directory = std::env::temp_dir().join(format!("pound-recover-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("notes.txt");
+    std::fs::write(&path, "saved\n").unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(std::time::SystemTime::UNIX_EPOCH).unwrap();
+    std::fs::write(directory.join(".notes.txt.pound.swp"), "recovered\n").unwrap();
+
+    let mut events = typed("y");
+    events.push(key(KeyCode::Enter));
+    events.extend(typed("x"));
+    events.push(ctrl('z'));
+    let (config, _) = Config::parse("remember_position = false").unwrap();
+    let editor_rows = EditorRows::from_file(path, 8).unwrap();
+    let keys = ScriptedKeys::new(events);
+    let mut editor = Editor::scripted(editor_rows, config, TERMINAL_SIZE, keys);
+    editor.recover().unwrap();
+    while editor.run().is_ok_and(|running| running) {}
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(rows(&editor), ["recovered"]);
+    assert!(editor.output().is_dirty());
+}